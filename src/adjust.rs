@@ -0,0 +1,64 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{DynamicImage, Rgb, RgbImage};
+use schemars::JsonSchema;
+use serde_derive::Deserialize;
+
+use crate::color;
+
+// Changes made to a cover before it's printed, since Zink prints come out darker and duller than
+// they look on screen, eg. `adjust = { brightness = 1.1, saturation = 1.2 }`. Each is a factor
+// like the CSS filters of the same names, so 1 leaves the cover as it is, 0.5 halves it and 2
+// doubles it.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+pub struct Adjustments {
+    pub brightness: Option<f32>,
+    pub contrast: Option<f32>,
+    pub saturation: Option<f32>,
+}
+
+impl Adjustments {
+    pub fn adjust_pixel(&self, Rgb(pixel): Rgb<u8>) -> Rgb<u8> {
+        let (brightness, contrast, saturation) = (
+            self.brightness.unwrap_or(1.0), self.contrast.unwrap_or(1.0),
+            self.saturation.unwrap_or(1.0),
+        );
+
+        let to_u8 = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+        let rgb = pixel.map(|c| (c as f32 * brightness - 127.5) * contrast + 127.5);
+
+        // Saturation is changed by moving each channel towards or away from the pixel's gray
+        let gray = color::luminance(Rgb(rgb.map(to_u8))) * 255.0;
+        Rgb(rgb.map(|c| to_u8(gray + (c - gray) * saturation)))
+    }
+
+    // Apply the adjustments to a cover. Covers that aren't adjusted are passed back untouched
+    pub fn apply(&self, cover: DynamicImage) -> DynamicImage {
+        if self.brightness.is_none() && self.contrast.is_none() && self.saturation.is_none() {
+            return cover;
+        }
+
+        let mut image: RgbImage = cover.into_rgb8();
+        for pixel in image.pixels_mut() {
+            *pixel = self.adjust_pixel(*pixel);
+        }
+        DynamicImage::ImageRgb8(image)
+    }
+}
@@ -0,0 +1,266 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use lofty::file::FileType;
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+
+use crate::{Label, Track};
+
+// Cover images that rippers and music players commonly leave alongside an album
+const COVER_NAMES: [&str; 6] =
+    ["cover.jpg", "cover.png", "folder.jpg", "folder.png", "front.jpg", "front.png"];
+
+// The tags read from a single audio file
+struct AudioTrack {
+    path: PathBuf,
+    album: Option<String>,
+    artist: Option<String>,
+    performer: Option<String>,
+    year: Option<String>,
+    genre: Option<String>,
+    title: String,
+    disc: u32,
+    number: u32,
+    duration: Duration,
+    picture: Option<Vec<u8>>,
+}
+
+fn read_track(path: &Path) -> Result<AudioTrack, Box<dyn Error>> {
+    let tagged = lofty::read_from_path(path)
+        .map_err(|e| format!("can't read tags from '{}': {}", path.display(), e))?;
+    let tag = tagged.primary_tag().or(tagged.first_tag());
+    let string = |key: ItemKey| tag.and_then(|tag| tag.get_string(key)).map(str::to_string);
+
+    Ok(AudioTrack {
+        path: path.to_path_buf(),
+        album: tag.and_then(|tag| tag.album()).map(|album| album.to_string()),
+        artist: string(ItemKey::AlbumArtist)
+            .or(tag.and_then(|tag| tag.artist()).map(|artist| artist.to_string())),
+        performer: tag.and_then(|tag| tag.artist()).map(|artist| artist.to_string()),
+        year: tag.and_then(|tag| tag.date()).map(|date| date.year.to_string()),
+        genre: tag.and_then(|tag| tag.genre()).map(|genre| genre.to_string()),
+        title: tag.and_then(|tag| tag.title()).map_or_else(
+            || path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            |title| title.to_string()
+        ),
+        disc: tag.and_then(|tag| tag.disk()).unwrap_or(0),
+        number: tag.and_then(|tag| tag.track()).unwrap_or(0),
+        duration: tagged.properties().duration(),
+        // Prefer the front cover, but any picture is better than none
+        picture: tag.and_then(|tag| {
+            let pictures = tag.pictures();
+            pictures.iter().find(|picture| picture.pic_type() == PictureType::CoverFront)
+                .or(pictures.first())
+                .map(|picture| picture.data().to_vec())
+        }),
+    })
+}
+
+// Durations are written as minutes and seconds, eg. "4:05", or "1:02:30" for an hour or more
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// Find a cover image that's been left in a directory alongside the music
+pub fn folder_cover(dir: &Path) -> Option<String> {
+    COVER_NAMES.iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+// How long an audio file plays for, if it can be read
+pub fn duration(path: &Path) -> Option<Duration> {
+    lofty::read_from_path(path).ok().map(|tagged| tagged.properties().duration())
+}
+
+// Find the audio files in a directory, and in every directory below it if `recursive` is set
+fn audio_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && recursive {
+            audio_files(&path, recursive, files)?;
+        } else if path.is_file() && FileType::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Build a label for an album from its tracks. The album details are taken from the first track
+// that has them, and the tracks are listed in disc and track number order. The cover is the
+// picture embedded in the tags, or an image in the album's directory if none of them have one.
+fn album_label(mut tracks: Vec<AudioTrack>) -> Label {
+    tracks.sort_by(|a, b| (a.disc, a.number, &a.path).cmp(&(b.disc, b.number, &b.path)));
+
+    let first = |field: fn(&AudioTrack) -> &Option<String>| {
+        tracks.iter().find_map(|track| field(track).clone())
+    };
+    let cover_data = tracks.iter().find_map(|track| track.picture.clone());
+    let cover = tracks[0].path.parent()
+        .filter(|_| cover_data.is_none())
+        .and_then(folder_cover);
+
+    Label {
+        title: first(|track| &track.album).unwrap_or_else(|| tracks[0].title.clone()),
+        artist: first(|track| &track.artist).unwrap_or_default(),
+        release_year: first(|track| &track.year),
+        genre: first(|track| &track.genre),
+        cover: cover.unwrap_or_default(),
+        cover_data,
+        tracks: tracks.iter().map(|track| Track::Detailed {
+            title: track.title.clone(),
+            duration: Some(format_duration(track.duration)),
+        }).collect(),
+        ..Default::default()
+    }
+}
+
+// Build a label from the tags of an audio file, or of every audio file in a directory
+pub fn label_from_audio(path: &str) -> Result<Label, Box<dyn Error>> {
+    let path = Path::new(path);
+    let mut files = Vec::new();
+    if path.is_dir() {
+        audio_files(path, false, &mut files)?;
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    let tracks = files.iter()
+        .map(|file| read_track(file))
+        .collect::<Result<Vec<_>, _>>()?;
+    if tracks.is_empty() {
+        return Err(format!("there are no audio files in '{}'", path.display()).into());
+    }
+    Ok(album_label(tracks))
+}
+
+// Walk a music library and make a label for every album in it. Tracks are grouped into albums
+// by their artist and album tags, or by directory when they have no album tag. Files whose tags
+// can't be read are skipped with a warning rather than stopping the scan.
+pub fn scan_library(root: &str) -> Result<Vec<Label>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    audio_files(Path::new(root), true, &mut files)?;
+
+    let mut albums: BTreeMap<(String, String), Vec<AudioTrack>> = BTreeMap::new();
+    for file in files {
+        let track = match read_track(&file) {
+            Ok(track) => track,
+            Err(e) => {
+                eprintln!("warning: skipping {}", e);
+                continue;
+            },
+        };
+
+        let album = track.album.clone().unwrap_or_else(|| {
+            track.path.parent().unwrap_or(Path::new("")).to_string_lossy().to_string()
+        });
+        albums.entry((track.artist.clone().unwrap_or_default(), album)).or_default().push(track);
+    }
+
+    if albums.is_empty() {
+        return Err(format!("there are no audio files in '{}'", root).into());
+    }
+    Ok(albums.into_values().map(album_label).collect())
+}
+
+// Build a label for a mix from an M3U playlist. Tracks are read from the tags of the files it
+// lists, falling back to the playlist's own `#EXTINF` details for anything that can't be read.
+// The label is titled after the playlist, and is by whoever is on every track, or by various
+// artists with each track listed along with its artist.
+pub fn label_from_playlist(path: &str) -> Result<Label, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("can't read '{}': {}", path, e))?;
+    let playlist = String::from_utf8_lossy(&bytes);
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut title = Path::new(path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let mut entries: Vec<(Option<String>, String, Option<Duration>)> = Vec::new();
+    let mut extinf: Option<(Option<Duration>, String)> = None;
+
+    for line in playlist.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (seconds, name) = info.split_once(',').unwrap_or((info, ""));
+            let duration = seconds.trim().parse::<f64>().ok()
+                .filter(|seconds| *seconds >= 0.0)
+                .map(Duration::from_secs_f64);
+            extinf = Some((duration, name.trim().to_string()));
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            title = name.trim().to_string();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let info = extinf.take();
+        match (read_track(&dir.join(line)), info) {
+            (Ok(track), _) => entries.push((track.performer, track.title, Some(track.duration))),
+            (Err(e), Some((duration, name))) => {
+                eprintln!("warning: {}, using the playlist's details instead", e);
+                let (artist, title) = match name.split_once(" - ") {
+                    Some((artist, title)) => (Some(artist.to_string()), title.to_string()),
+                    None => (None, name),
+                };
+                entries.push((artist, title, duration));
+            },
+            (Err(e), None) => eprintln!("warning: skipping {}", e),
+        }
+    }
+
+    if entries.is_empty() {
+        return Err(format!("'{}' doesn't list any tracks that can be read", path).into());
+    }
+
+    let artist = entries[0].0.clone()
+        .filter(|artist| entries.iter().all(|(other, _, _)| other.as_ref() == Some(artist)));
+    let runtime = entries.iter()
+        .map(|(_, _, duration)| *duration)
+        .sum::<Option<Duration>>()
+        .map(format_duration);
+
+    let tracks = entries.into_iter().map(|(track_artist, title, duration)| Track::Detailed {
+        title: match track_artist {
+            Some(track_artist) if artist.is_none() => format!("{} \u{2014} {}", track_artist, title),
+            _ => title,
+        },
+        duration: duration.map(format_duration),
+    }).collect();
+
+    Ok(Label {
+        title,
+        artist: artist.unwrap_or("Various Artists".to_string()),
+        tracks,
+        runtime,
+        ..Default::default()
+    })
+}
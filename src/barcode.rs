@@ -0,0 +1,158 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use ab_glyph::PxScale;
+use imageproc::image::{Rgb, RgbImage};
+
+use crate::fonts::LabelFont;
+use crate::{text, TextLine, TextOrientation};
+
+// Left hand odd parity patterns for each digit. Even parity patterns are these reversed and
+// inverted, and right hand patterns are these inverted.
+const L_CODES: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011,
+    0b0110001, 0b0101111, 0b0111011, 0b0110111, 0b0001011,
+];
+
+// The first digit isn't drawn as bars, but picks which of the left hand digits use even parity
+const PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011,
+    0b011001, 0b011100, 0b010101, 0b010110, 0b011010,
+];
+
+// Light modules either side of the bars, which the first and last digits are printed in
+const LEFT_QUIET_ZONE: u32 = 11;
+const RIGHT_QUIET_ZONE: u32 = 7;
+const BARS: u32 = 95;
+
+// Height of the digits under the bars, in modules
+const DIGIT_SIZE: u32 = 10;
+
+fn check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits.iter().enumerate()
+        .map(|(i, &digit)| digit as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+// Parse a 13 digit EAN or a 12 digit UPC-A, which is an EAN starting with 0, and check that its
+// check digit is right
+fn parse(code: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut digits: Vec<u8> = code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_digit(10).map(|d| d as u8))
+        .collect::<Option<_>>()
+        .ok_or(format!("barcode '{}' has something other than digits in it", code))?;
+
+    match digits.len() {
+        12 => digits.insert(0, 0),
+        13 => (),
+        _ => return Err(format!("barcode '{}' isn't a 12 digit UPC or 13 digit EAN", code).into()),
+    }
+
+    let check = check_digit(&digits[..12]);
+    if digits[12] != check {
+        return Err(format!("barcode '{}' should end in {}", code, check).into());
+    }
+    Ok(digits)
+}
+
+// The 95 modules of an EAN-13 barcode, true for dark, and whether each is part of a guard
+// pattern, which are drawn longer than the bars for the digits
+fn modules(digits: &[u8]) -> Vec<(bool, bool)> {
+    let mut modules = Vec::new();
+    let mut push = |pattern: u8, width: u32, guard: bool| {
+        for bit in (0..width).rev() {
+            modules.push((pattern >> bit & 1 == 1, guard));
+        }
+    };
+
+    push(0b101, 3, true);
+    for (i, &digit) in digits[1..7].iter().enumerate() {
+        let code = L_CODES[digit as usize];
+        if PARITY[digits[0] as usize] >> (5 - i) & 1 == 1 {
+            push((!code & 0x7f).reverse_bits() >> 1, 7, false);
+        } else {
+            push(code, 7, false);
+        }
+    }
+    push(0b01010, 5, true);
+    for &digit in &digits[7..] {
+        push(!L_CODES[digit as usize] & 0x7f, 7, false);
+    }
+    push(0b101, 3, true);
+    modules
+}
+
+// Draw an EAN-13 or UPC-A barcode, with its digits underneath, as big as fits in `width` x
+// `height` pixels. Modules are a whole number of pixels across so that the bars stay sharp.
+pub fn draw_barcode(
+    code: &str,
+    font: &LabelFont,
+    width: u32,
+    height: u32
+) -> Result<RgbImage, Box<dyn Error>> {
+    let digits = parse(code)?;
+    let module = (width / (LEFT_QUIET_ZONE + BARS + RIGHT_QUIET_ZONE)).max(1);
+    let digit_size = DIGIT_SIZE * module;
+    let bar_height = height.saturating_sub(digit_size);
+
+    let mut image = RgbImage::from_pixel(
+        (LEFT_QUIET_ZONE + BARS + RIGHT_QUIET_ZONE) * module, height, Rgb([255, 255, 255])
+    );
+    for (i, (dark, guard)) in modules(&digits).into_iter().enumerate() {
+        if !dark {
+            continue;
+        }
+        let bottom = if guard { bar_height + digit_size / 2 } else { bar_height };
+        let x = (LEFT_QUIET_ZONE + i as u32) * module;
+        for y in 0..bottom.min(height) {
+            for dx in 0..module {
+                image.put_pixel(x + dx, y, Rgb([0, 0, 0]));
+            }
+        }
+    }
+
+    // The first digit goes in the quiet zone, and the rest in groups of six under each half
+    let scale = PxScale::from(digit_size as f32);
+    let groups = [
+        (&digits[..1], 0),
+        (&digits[1..7], LEFT_QUIET_ZONE + 3),
+        (&digits[7..], LEFT_QUIET_ZONE + 50),
+    ];
+    for (group, start) in groups {
+        let text: String = group.iter().map(|digit| char::from(b'0' + digit)).collect();
+        let space = if group.len() == 1 { LEFT_QUIET_ZONE - 1 } else { 42 } * module;
+        let length = text::text_length(&text, font, scale, TextOrientation::Horizontal);
+        let line = TextLine {
+            x: (start * module) as i32 + ((space as f32 - length) / 2.0).round() as i32,
+            y: bar_height as i32,
+            text,
+            font: font.clone(),
+            scale,
+            orientation: TextOrientation::Horizontal,
+            color: Rgb([0, 0, 0]),
+        };
+        text::draw_line(&mut image, &line);
+    }
+
+    Ok(image)
+}
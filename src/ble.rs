@@ -0,0 +1,282 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::time::Duration;
+
+use btleplug::api::bleuuid::uuid_from_u16;
+use btleplug::api::{Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType};
+use btleplug::api::ValueNotification;
+use btleplug::platform::{Manager, Peripheral};
+use futures_util::{Stream, StreamExt};
+use imageproc::image::RgbImage;
+
+use crate::dither::Dither;
+use crate::{escpos, thermal};
+
+// The makes of printer that can be printed on, which each have their own way of being sent labels
+#[derive(Clone, Copy, Debug)]
+enum Make {
+    Niimbot,
+    Phomemo,
+}
+
+// Printers are recognised by the start of the name they advertise, and print this many dots
+// across. Longer names come first, so that a D110 isn't taken for a D11
+const MODELS: [(&str, Make, u32); 8] = [
+    ("D110", Make::Niimbot, 96),
+    ("D101", Make::Niimbot, 192),
+    ("D11", Make::Niimbot, 96),
+    ("B21", Make::Niimbot, 384),
+    ("B1", Make::Niimbot, 384),
+    ("M110", Make::Phomemo, 384),
+    ("M02", Make::Phomemo, 384),
+    ("T02", Make::Phomemo, 384),
+];
+
+// All of them print at 203 dpi
+const DOTS_PER_MM: f32 = 203.0 / 25.4;
+
+// How long to look for printers for, and to wait for one to answer
+const SCAN_TIME: Duration = Duration::from_secs(5);
+const RESPONSE_TIME: Duration = Duration::from_secs(10);
+
+// Niimbots take commands and answer them on one characteristic
+const NIIMBOT_CHARACTERISTIC: &str = "bef8d6c9-9c21-4c9e-b632-bd58c1009f9f";
+
+// Phomemos take ESC/POS raster commands on a characteristic of their own, a little at a time so
+// that they can keep up
+const PHOMEMO_CHARACTERISTIC: u16 = 0xff02;
+const PHOMEMO_CHUNK: usize = 128;
+const PHOMEMO_PAUSE: Duration = Duration::from_millis(20);
+
+// A printer found nearby
+struct Printer {
+    peripheral: Peripheral,
+    name: String,
+    make: Make,
+    dots: u32,
+}
+
+impl Printer {
+    fn width_mm(&self) -> f32 {
+        (self.dots as f32 / DOTS_PER_MM).round()
+    }
+
+    // The characteristic labels are sent to
+    fn characteristic(&self) -> Result<Characteristic, Box<dyn Error>> {
+        self.peripheral.characteristics().into_iter()
+            .find(|characteristic| match self.make {
+                Make::Niimbot => characteristic.uuid.to_string() == NIIMBOT_CHARACTERISTIC,
+                Make::Phomemo => characteristic.uuid == uuid_from_u16(PHOMEMO_CHARACTERISTIC),
+            })
+            .ok_or(format!("{} doesn't look like a {:?} printer", self.name, self.make).into())
+    }
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime, Box<dyn Error>> {
+    Ok(tokio::runtime::Builder::new_multi_thread().enable_all().build()?)
+}
+
+// Look for printers on the first Bluetooth adapter, keeping the ones that are recognised
+async fn scan() -> Result<Vec<Printer>, Box<dyn Error>> {
+    let manager = Manager::new().await.map_err(|e| format!("can't use Bluetooth: {}", e))?;
+    let adapter = manager.adapters().await?.into_iter().next()
+        .ok_or("there's no Bluetooth adapter to look for printers with")?;
+    adapter.start_scan(ScanFilter::default()).await?;
+    tokio::time::sleep(SCAN_TIME).await;
+    adapter.stop_scan().await?;
+
+    let mut printers = Vec::new();
+    for peripheral in adapter.peripherals().await? {
+        let Some(name) = peripheral.properties().await?.and_then(|properties| properties.local_name)
+        else {
+            continue;
+        };
+        if let Some((_, make, dots)) = MODELS.iter().find(|(model, _, _)| name.starts_with(model)) {
+            printers.push(Printer { peripheral, name, make: *make, dots: *dots });
+        }
+    }
+    Ok(printers)
+}
+
+// Print the printers that are nearby, with the names and addresses they can be picked by
+pub fn list_devices() -> Result<(), Box<dyn Error>> {
+    let printers = runtime()?.block_on(scan())?;
+    if printers.is_empty() {
+        println!("No label printers found. Check that they're switched on, and not connected to \
+            anything else");
+    }
+    for printer in printers {
+        println!("{}\t{}\t{:?}, {} mm", printer.name, printer.peripheral.address(), printer.make,
+            printer.width_mm());
+    }
+    Ok(())
+}
+
+// A Niimbot packet, which is framed by 0x55s and 0xaas and checked by XORing its bytes together
+fn niimbot_packet(command: u8, data: &[u8]) -> Vec<u8> {
+    let checksum = data.iter().fold(command ^ data.len() as u8, |checksum, byte| checksum ^ byte);
+    [&[0x55, 0x55, command, data.len() as u8], data, &[checksum, 0xaa, 0xaa]].concat()
+}
+
+// Send a Niimbot a command and wait for its answer, returning whatever data it sent back
+async fn niimbot_request(
+    printer: &Printer,
+    characteristic: &Characteristic,
+    answers: &mut (impl Stream<Item = ValueNotification> + Unpin),
+    command: (u8, u8),
+    data: &[u8]
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (command, answer) = command;
+    let packet = niimbot_packet(command, data);
+    printer.peripheral.write(characteristic, &packet, WriteType::WithoutResponse).await?;
+    loop {
+        let notification = tokio::time::timeout(RESPONSE_TIME, answers.next()).await
+            .map_err(|_| format!("{} stopped answering", printer.name))?
+            .ok_or(format!("{} disconnected", printer.name))?;
+        let packet = notification.value;
+        if packet.len() >= 7 && packet[..2] == [0x55, 0x55] && packet[2] == answer {
+            let length = packet[3] as usize;
+            return Ok(packet.get(4..4 + length).ok_or("the printer sent back a broken packet")?
+                .to_vec());
+        }
+    }
+}
+
+// Commands that are answered with whether they worked, keep being sent until they have
+async fn niimbot_until_done(
+    printer: &Printer,
+    characteristic: &Characteristic,
+    answers: &mut (impl Stream<Item = ValueNotification> + Unpin),
+    command: (u8, u8)
+) -> Result<(), Box<dyn Error>> {
+    let start = tokio::time::Instant::now();
+    loop {
+        let done = niimbot_request(printer, characteristic, answers, command, &[1]).await?;
+        if done.first() == Some(&1) {
+            return Ok(());
+        }
+        if start.elapsed() > RESPONSE_TIME {
+            return Err(format!("{} didn't finish printing", printer.name).into());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+// Print on a Niimbot. Each label is a page, sent a line at a time with the line's number
+async fn niimbot(printer: &Printer, labels: &[RgbImage]) -> Result<(), Box<dyn Error>> {
+    let characteristic = printer.characteristic()?;
+    printer.peripheral.subscribe(&characteristic).await?;
+    let mut answers = printer.peripheral.notifications().await?;
+
+    // The middle density, on labels with gaps between them
+    niimbot_request(printer, &characteristic, &mut answers, (0x21, 0x31), &[3]).await?;
+    niimbot_request(printer, &characteristic, &mut answers, (0x23, 0x33), &[1]).await?;
+    niimbot_until_done(printer, &characteristic, &mut answers, (0x01, 0x02)).await?;
+
+    for label in labels {
+        niimbot_until_done(printer, &characteristic, &mut answers, (0x03, 0x04)).await?;
+        let size = [(label.height() as u16).to_be_bytes(), (label.width() as u16).to_be_bytes()];
+        niimbot_request(printer, &characteristic, &mut answers, (0x13, 0x14), &size.concat())
+            .await?;
+        for (y, row) in label.rows().enumerate() {
+            let line: Vec<bool> = row.map(thermal::is_black).collect();
+            let data = [&(y as u16).to_be_bytes()[..], &[0, 0, 0, 1], &thermal::pack(&line)]
+                .concat();
+            printer.peripheral.write(&characteristic, &niimbot_packet(0x85, &data),
+                WriteType::WithoutResponse).await?;
+        }
+        niimbot_until_done(printer, &characteristic, &mut answers, (0xe3, 0xe4)).await?;
+    }
+
+    // Wait for every page to come out before finishing
+    let start = tokio::time::Instant::now();
+    loop {
+        let status = niimbot_request(printer, &characteristic, &mut answers, (0xa3, 0xb3), &[1])
+            .await?;
+        if let [high, low, ..] = status[..] {
+            if u16::from_be_bytes([high, low]) as usize >= labels.len() {
+                break;
+            }
+        }
+        if start.elapsed() > RESPONSE_TIME * labels.len() as u32 {
+            return Err(format!("{} didn't finish printing", printer.name).into());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    niimbot_until_done(printer, &characteristic, &mut answers, (0xf3, 0xf4)).await
+}
+
+// Print on a Phomemo, as ESC/POS raster images with a little paper fed after each label
+async fn phomemo(printer: &Printer, labels: &[RgbImage]) -> Result<(), Box<dyn Error>> {
+    let characteristic = printer.characteristic()?;
+
+    let mut job = vec![0x1b, 0x40, 0x1b, 0x61, 0x01, 0x1f, 0x11, 0x02, 0x04];
+    for label in labels {
+        job.extend(escpos::raster_image(label));
+        job.extend([0x1b, 0x64, 0x02, 0x1b, 0x64, 0x02]);
+    }
+
+    for chunk in job.chunks(PHOMEMO_CHUNK) {
+        printer.peripheral.write(&characteristic, chunk, WriteType::WithoutResponse).await?;
+        tokio::time::sleep(PHOMEMO_PAUSE).await;
+    }
+    Ok(())
+}
+
+// Print each label on its own on a Bluetooth label printer, given by the name or address it was
+// listed with, or the first printer found when `device` is empty. Labels are scaled to the
+// printer's resolution, turned on their side if that's the only way they fit across it, and
+// dithered to black and white.
+pub fn print(
+    device: &str,
+    labels: &[RgbImage],
+    dpmm: f32,
+    dither: Dither
+) -> Result<(), Box<dyn Error>> {
+    runtime()?.block_on(async {
+        let printer = scan().await?.into_iter()
+            .find(|printer| device.is_empty()
+                || printer.name.eq_ignore_ascii_case(device)
+                || printer.peripheral.address().to_string().eq_ignore_ascii_case(device))
+            .ok_or(match device {
+                "" => "there are no label printers nearby".to_string(),
+                device => format!("can't find the printer '{}', use --list-devices to see the \
+                    printers that are nearby", device),
+            })?;
+
+        let head = format!("{}'s {} mm head", printer.name, printer.width_mm());
+        let labels = labels.iter()
+            .map(|label| thermal::dots(label, dpmm, DOTS_PER_MM, printer.dots, &head, dither))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        printer.peripheral.connect().await?;
+        printer.peripheral.discover_services().await?;
+        let printed = match printer.make {
+            Make::Niimbot => niimbot(&printer, &labels).await,
+            Make::Phomemo => phomemo(&printer, &labels).await,
+        };
+        printer.peripheral.disconnect().await?;
+        printed?;
+
+        println!("Printed {} labels on {}", labels.len(), printer.name);
+        Ok(())
+    })
+}
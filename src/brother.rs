@@ -0,0 +1,79 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use imageproc::image::RgbImage;
+
+use crate::dither::Dither;
+use crate::thermal;
+
+// The print head prints 300 dots to the inch, 720 dots across. 62 mm tape only covers 696 of
+// them, starting 12 dots in from the end the lines are sent from
+const DOTS_PER_MM: f32 = 300.0 / 25.4;
+const HEAD_DOTS: usize = 720;
+const TAPE_DOTS: u32 = 696;
+const TAPE_OFFSET: usize = 12;
+const TAPE_WIDTH_MM: u8 = 62;
+
+// The media type byte for continuous tape, as opposed to die-cut labels
+const CONTINUOUS_TAPE: u8 = 0x0a;
+
+// Tape fed before and after each label, in dots, which is the smallest continuous tape allows
+const FEED_MARGIN: u16 = 35;
+
+// Turn a sheet into a print job for a Brother QL printer, like the QL-700 or QL-800, loaded with
+// 62 mm continuous tape. The sheet is scaled to the printer's 300 dpi, turned on its side if
+// that's the only way it fits across the tape, and dithered to black and white. The job prints a
+// single label and cuts it off, and can be sent straight to the printer, eg. by copying it to
+// `/dev/usb/lp0` or with `lp -o raw`.
+pub fn raster(sheet: &RgbImage, dpmm: f32, dither: Dither) -> Result<Vec<u8>, Box<dyn Error>> {
+    let tape = format!("{} mm tape", TAPE_WIDTH_MM);
+    let dots = thermal::dots(sheet, dpmm, DOTS_PER_MM, TAPE_DOTS, &tape, dither)?;
+
+    let mut job = vec![0; 200];
+    // Initialise, and switch to raster mode
+    job.extend([0x1b, 0x40, 0x1b, 0x69, 0x61, 0x01]);
+    // The media and how many lines there are, with the valid fields being the media type, its
+    // width, print quality and recovery being on
+    job.extend([0x1b, 0x69, 0x7a, 0xc6, CONTINUOUS_TAPE, TAPE_WIDTH_MM, 0]);
+    job.extend(dots.height().to_le_bytes());
+    job.extend([0, 0]);
+    // Cut automatically, after every label, and once more at the end
+    job.extend([0x1b, 0x69, 0x4d, 0x40, 0x1b, 0x69, 0x41, 0x01, 0x1b, 0x69, 0x4b, 0x08]);
+    job.extend([0x1b, 0x69, 0x64]);
+    job.extend(FEED_MARGIN.to_le_bytes());
+    // The lines aren't compressed
+    job.extend([0x4d, 0x00]);
+
+    // Each line is sent from the far end of the head, so the label is mirrored as it's packed.
+    // It's centred across the tape
+    let left = TAPE_OFFSET + (TAPE_DOTS - dots.width()) as usize / 2;
+    for row in dots.rows() {
+        let mut line = [false; HEAD_DOTS];
+        for (x, pixel) in row.enumerate() {
+            line[HEAD_DOTS - 1 - (left + x)] = thermal::is_black(pixel);
+        }
+        job.extend([0x67, 0x00, (HEAD_DOTS / 8) as u8]);
+        job.extend(thermal::pack(&line));
+    }
+    // Print, feeding the tape out to be cut
+    job.push(0x1a);
+    Ok(job)
+}
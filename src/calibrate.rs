@@ -0,0 +1,145 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use ab_glyph::PxScale;
+use imageproc::image::{Rgb, RgbImage};
+
+use crate::fonts::LabelFont;
+use crate::{text, Metrics, TextLine, TextOrientation};
+
+// Sizes on the test sheet in mm. The grid starts from the top left corner, and the registration
+// marks are the same distance in from each edge
+const GRID_SPACING: usize = 10;
+const MARK_INSET: f32 = 10.0;
+const MARK_RADIUS: f32 = 2.5;
+const MARK_LENGTH: f32 = 8.0;
+const LINE_THICKNESS: f32 = 0.15;
+const TEXT_SIZE: f32 = 2.5;
+
+const PAPER: Rgb<u8> = Rgb([255, 255, 255]);
+const INK: Rgb<u8> = Rgb([0, 0, 0]);
+const GRID_COLOR: Rgb<u8> = Rgb([190, 190, 190]);
+
+// Ruler ticks are longest at every cm and half cm, like on a real ruler
+fn tick_length(mm: usize) -> f32 {
+    match mm {
+        mm if mm % 10 == 0 => 4.0,
+        mm if mm % 5 == 0 => 2.5,
+        _ => 1.5,
+    }
+}
+
+// Fill in a rectangle of the sheet, clipped to its edges
+fn fill(image: &mut RgbImage, (x, y): (i32, i32), (width, height): (i32, i32), color: Rgb<u8>) {
+    let (image_width, image_height) = (image.width() as i32, image.height() as i32);
+    for py in y.max(0)..(y + height).min(image_height) {
+        for px in x.max(0)..(x + width).min(image_width) {
+            image.put_pixel(px as u32, py as u32, color);
+        }
+    }
+}
+
+// Draw a ring centred on a point, `thickness` pixels wide
+fn ring(image: &mut RgbImage, (x, y): (i32, i32), radius: i32, thickness: i32) {
+    let outer = radius + thickness;
+    for py in y - outer..=y + outer {
+        for px in x - outer..=x + outer {
+            let distance = (((px - x).pow(2) + (py - y).pow(2)) as f32).sqrt();
+            if (distance - radius as f32).abs() <= thickness as f32 / 2.0 {
+                fill(image, (px, py), (1, 1), INK);
+            }
+        }
+    }
+}
+
+// A test sheet to print and measure, to find how far a printer scales and shifts what it prints.
+// There's a grid of 10mm squares over the whole sheet, rulers in mm along its top and left edges,
+// and registration marks a set distance in from each corner. Comparing where the marks land with
+// where they should be gives the offset, and measuring the grid gives the scale.
+pub fn test_sheet(metrics: &Metrics) -> RgbImage {
+    let (width, height) = (metrics.printable_width_px, metrics.printable_height_px);
+    let (width_mm, height_mm) = metrics.printable_mm;
+    let mut image = RgbImage::from_pixel(width, height, PAPER);
+    let line = metrics.px(LINE_THICKNESS).max(1);
+    let px = |mm: f32| metrics.px(mm);
+
+    for mm in (0..=width_mm as usize).step_by(GRID_SPACING) {
+        fill(&mut image, (px(mm as f32) - line / 2, 0), (line, height as i32), GRID_COLOR);
+    }
+    for mm in (0..=height_mm as usize).step_by(GRID_SPACING) {
+        fill(&mut image, (0, px(mm as f32) - line / 2), (width as i32, line), GRID_COLOR);
+    }
+
+    let font = LabelFont::bundled();
+    let scale = PxScale::from(TEXT_SIZE * metrics.dpmm);
+    let mut lines = Vec::new();
+    let mut write = |text: String, x: i32, y: i32| lines.push(TextLine {
+        text,
+        font: font.clone(),
+        x,
+        y,
+        scale,
+        orientation: TextOrientation::Horizontal,
+        color: INK,
+    });
+
+    // The rulers are numbered in mm at every cm, beside the tick for it
+    for mm in 0..=width_mm as usize {
+        let x = px(mm as f32) - line / 2;
+        fill(&mut image, (x, 0), (line, px(tick_length(mm))), INK);
+        if mm % 10 == 0 && mm > 0 {
+            write(mm.to_string(), x + 2 * line, px(tick_length(mm) - TEXT_SIZE));
+        }
+    }
+    for mm in 0..=height_mm as usize {
+        let y = px(mm as f32) - line / 2;
+        fill(&mut image, (0, y), (px(tick_length(mm)), line), INK);
+        if mm % 10 == 0 && mm > 0 {
+            write(mm.to_string(), px(tick_length(mm)) + 2 * line, y + 2 * line);
+        }
+    }
+
+    let (left, top) = (px(MARK_INSET), px(MARK_INSET));
+    let (right, bottom) = (px(width_mm - MARK_INSET), px(height_mm - MARK_INSET));
+    let length = px(MARK_LENGTH);
+    for (x, y) in [(left, top), (right, top), (left, bottom), (right, bottom)] {
+        fill(&mut image, (x - length / 2, y - line / 2), (length, line), INK);
+        fill(&mut image, (x - line / 2, y - length / 2), (line, length), INK);
+        ring(&mut image, (x, y), px(MARK_RADIUS), line);
+    }
+
+    // What the sheet should measure, in the middle of it
+    let dpi = (metrics.dpmm * crate::MM_PER_INCH).round();
+    let notes = [
+        format!("{} x {} mm at {} dpi", width_mm, height_mm, dpi),
+        format!("{} mm grid, marks {} mm from the edges", GRID_SPACING, MARK_INSET),
+    ];
+    let line_height = (TEXT_SIZE * crate::LINE_SPACING * metrics.dpmm).round() as i32;
+    let mut y = height as i32 / 2 - line_height;
+    for note in notes {
+        let length = text::text_length(&note, &font, scale, TextOrientation::Horizontal);
+        write(note, (width as f32 - length).round() as i32 / 2, y);
+        y += line_height;
+    }
+
+    for line in &lines {
+        text::draw_line(&mut image, line);
+    }
+    image
+}
@@ -0,0 +1,81 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{imageops, DynamicImage, GrayImage};
+
+// Covers are measured at no more than this many pixels along their longest side, which is plenty
+// to tell the busy parts of an image from the plain ones
+const SAMPLE_SIZE: u32 = 256;
+// How many places along the cover the crop is tried in
+const POSITIONS: u32 = 32;
+
+// How much detail there is in part of an image, as the entropy of its brightness histogram. Flat
+// areas like a plain background score low, and faces, text and detailed art score high.
+fn entropy(image: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> f32 {
+    let mut histogram = [0u32; 256];
+    for py in y..y + height {
+        for px in x..x + width {
+            histogram[image.get_pixel(px, py)[0] as usize] += 1;
+        }
+    }
+
+    let total = (width * height).max(1) as f32;
+    histogram.iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f32 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Find the part of a cover with the same shape as a `width` by `height` area that has the most
+// detail in it, as `(x, y, width, height)` in the cover's pixels. The crop is as large as it can
+// be, so it only slides along the cover's longer side.
+pub fn smart_crop(cover: &DynamicImage, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (cover_width, cover_height) = (cover.width(), cover.height());
+    let wider = cover_width as u64 * height as u64 > cover_height as u64 * width as u64;
+    let (crop_width, crop_height) = if wider {
+        ((cover_height as u64 * width as u64 / height as u64) as u32, cover_height)
+    } else {
+        (cover_width, (cover_width as u64 * height as u64 / width as u64) as u32)
+    };
+
+    let scale = (SAMPLE_SIZE as f32 / cover_width.max(cover_height) as f32).min(1.0);
+    let sample = cover.resize(
+        ((cover_width as f32 * scale).round() as u32).max(1),
+        ((cover_height as f32 * scale).round() as u32).max(1),
+        imageops::FilterType::Triangle,
+    ).to_luma8();
+    let window_width = ((crop_width as f32 * scale).round() as u32).clamp(1, sample.width());
+    let window_height = ((crop_height as f32 * scale).round() as u32).clamp(1, sample.height());
+
+    let (slack_x, slack_y) = (sample.width() - window_width, sample.height() - window_height);
+    let (x, y) = (0..=POSITIONS)
+        .map(|step| (slack_x * step / POSITIONS, slack_y * step / POSITIONS))
+        .max_by(|a, b| {
+            entropy(&sample, a.0, a.1, window_width, window_height)
+                .total_cmp(&entropy(&sample, b.0, b.1, window_width, window_height))
+        })
+        .unwrap_or((0, 0));
+
+    let x = ((x as f32 / scale).round() as u32).min(cover_width - crop_width);
+    let y = ((y as f32 / scale).round() as u32).min(cover_height - crop_height);
+    (x, y, crop_width, crop_height)
+}
@@ -0,0 +1,123 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::audio;
+use crate::{Label, Track};
+
+// CUE sheet times are minutes, seconds and frames, with 75 frames to the second
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Default)]
+struct CueTrack {
+    file: Option<String>,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<Duration>,
+}
+
+// Split a CUE sheet line into its command and arguments, keeping quoted arguments together
+fn parse_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut rest = line.trim();
+
+    while !rest.is_empty() {
+        let (word, remaining) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        words.push(word.to_string());
+        rest = remaining.trim_start();
+    }
+    words
+}
+
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    let frames = (minutes * 60 + seconds) * FRAMES_PER_SECOND + frames;
+    Some(Duration::from_millis(frames * 1000 / FRAMES_PER_SECOND))
+}
+
+// Build a label from a CUE sheet, using the disc's PERFORMER and TITLE for the artist and title
+// and listing every track. Track lengths come from where the next track starts, or for the last
+// track in a file, from the length of the audio file if it's alongside the sheet. Tracks by a
+// different performer to the disc's are listed with their performer.
+pub fn label_from_cue(path: &str) -> Result<Label, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("can't read '{}': {}", path, e))?;
+    let sheet = String::from_utf8_lossy(&bytes);
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut label = Label::default();
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    let mut file: Option<String> = None;
+
+    for line in sheet.lines() {
+        let words = parse_line(line);
+        let Some(command) = words.first() else { continue };
+        let argument = words.get(1).cloned();
+
+        match (command.to_uppercase().as_str(), tracks.last_mut()) {
+            ("FILE", _) => file = argument,
+            ("TRACK", _) => tracks.push(CueTrack { file: file.clone(), ..Default::default() }),
+            ("TITLE", Some(track)) => track.title = argument,
+            ("PERFORMER", Some(track)) => track.performer = argument,
+            ("INDEX", Some(track)) if argument.as_deref() == Some("01") => {
+                track.start = words.get(2).and_then(|time| parse_time(time));
+            },
+            ("TITLE", None) => label.title = argument.unwrap_or_default(),
+            ("PERFORMER", None) => label.artist = argument.unwrap_or_default(),
+            ("REM", None) if argument.as_deref() == Some("DATE") => {
+                label.release_year = words.get(2).cloned();
+            },
+            _ => {},
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err(format!("'{}' doesn't list any tracks", path).into());
+    }
+
+    for (i, track) in tracks.iter().enumerate() {
+        let next = tracks.get(i + 1).filter(|next| next.file == track.file);
+        let end = match next {
+            Some(next) => next.start,
+            None => track.file.as_ref().and_then(|file| audio::duration(&dir.join(file))),
+        };
+        let duration = track.start.zip(end)
+            .and_then(|(start, end)| end.checked_sub(start))
+            .map(audio::format_duration);
+
+        let title = track.title.clone().unwrap_or_else(|| format!("Track {}", i + 1));
+        let title = match &track.performer {
+            Some(performer) if *performer != label.artist => {
+                format!("{} \u{2014} {}", performer, title)
+            },
+            _ => title,
+        };
+        label.tracks.push(Track::Detailed { title, duration });
+    }
+
+    label.cover = audio::folder_cover(dir).unwrap_or_default();
+    Ok(label)
+}
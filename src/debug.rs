@@ -0,0 +1,138 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{Rgb, RgbImage};
+
+use crate::layout::Rect;
+use crate::{text, Metrics, RenderedLabel};
+
+// The areas shown on a label, by the names its elements are kept under, and the color of each
+const AREAS: [(&str, Rgb<u8>); 4] = [
+    ("cover", Rgb([0, 110, 255])),
+    ("text-area", Rgb([0, 190, 70])),
+    ("logo", Rgb([255, 140, 0])),
+    ("qr-code", Rgb([255, 140, 0])),
+];
+const SAFE_COLOR: Rgb<u8> = Rgb([230, 0, 230]);
+const TRIM_COLOR: Rgb<u8> = Rgb([230, 0, 0]);
+
+// Boxes around each element of a layout, and around each line of text within them
+const ELEMENT_COLOR: Rgb<u8> = Rgb([0, 200, 220]);
+const LINE_COLOR: Rgb<u8> = Rgb([255, 200, 0]);
+
+// Areas are filled in faintly enough to see the artwork through them
+const FILL_OPACITY: f32 = 0.25;
+// Outlines are this thick, in mm
+const LINE_THICKNESS: f32 = 0.2;
+
+// Mix a color into a rectangle of the image, clipped to its edges
+fn blend(image: &mut RgbImage, rect: &Rect, color: Rgb<u8>, opacity: f32) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    for y in rect.y.max(0)..rect.bottom().min(height) {
+        for x in rect.x.max(0)..rect.right().min(width) {
+            let under = image.get_pixel_mut(x as u32, y as u32);
+            for c in 0..3 {
+                under[c] = (under[c] as f32 * (1.0 - opacity) + color[c] as f32 * opacity)
+                    .round() as u8;
+            }
+        }
+    }
+}
+
+// Draw a line just inside the edges of a rectangle
+fn outline(image: &mut RgbImage, rect: &Rect, color: Rgb<u8>, thickness: i32) {
+    let Rect { x, y, width, height } = *rect;
+    for edge in [
+        Rect { x, y, width, height: thickness },
+        Rect { x, y: rect.bottom() - thickness, width, height: thickness },
+        Rect { x, y, width: thickness, height },
+        Rect { x: rect.right() - thickness, y, width: thickness, height },
+    ] {
+        blend(image, &edge, color, 1.0);
+    }
+}
+
+// Draw the areas that a label's elements are laid out in over its artwork, along with where it's
+// trimmed and the safe zone inside that, which is the padding in from the trim. Anything of the
+// artwork that matters should be inside the safe zone, as printers and cutters can be out by
+// about that much.
+pub fn draw_safe_area(label: &mut RenderedLabel, metrics: &Metrics) {
+    let thickness = metrics.px(LINE_THICKNESS).max(1);
+    for (name, color) in AREAS {
+        if let Some(rect) = label.elements.get(name) {
+            blend(&mut label.art, rect, color, FILL_OPACITY);
+            outline(&mut label.art, rect, color, thickness);
+        }
+    }
+
+    let bleed = label.bleed;
+    let trim = Rect {
+        x: bleed,
+        y: bleed,
+        width: label.art.width() as i32 - 2 * bleed,
+        height: label.art.height() as i32 - 2 * bleed,
+    };
+    let inset = metrics.padding;
+    let safe = Rect {
+        x: trim.x + inset,
+        y: trim.y + inset,
+        width: trim.width - 2 * inset,
+        height: trim.height - 2 * inset,
+    };
+    outline(&mut label.art, &safe, SAFE_COLOR, thickness);
+    outline(&mut label.art, &trim, TRIM_COLOR, thickness);
+}
+
+// The elements of a label that were placed, in name order so they're listed the same way each time.
+// The text area isn't one of them, it's what they're placed within.
+fn placed_elements(label: &RenderedLabel) -> Vec<(&String, &Rect)> {
+    let mut elements: Vec<_> = label.elements.iter()
+        .filter(|(name, _)| name.as_str() != "text-area")
+        .collect();
+    elements.sort_by_key(|(name, _)| name.as_str());
+    elements
+}
+
+// Outline every element of a label and every line of its text over its artwork
+pub fn draw_layout(label: &mut RenderedLabel, metrics: &Metrics) {
+    let thickness = metrics.px(LINE_THICKNESS).max(1);
+    let rects: Vec<Rect> = placed_elements(label).into_iter().map(|(_, rect)| *rect).collect();
+    for rect in &rects {
+        outline(&mut label.art, rect, ELEMENT_COLOR, thickness);
+    }
+    for line in &label.text {
+        outline(&mut label.art, &text::line_bounds(line), LINE_COLOR, thickness);
+    }
+}
+
+// Write out where each element of a label and each line of its text ended up, in label pixels
+pub fn log_layout(label: &RenderedLabel, description: &str) {
+    eprintln!("layout: {} ({}x{} px, {} px bleed)",
+        description, label.art.width(), label.art.height(), label.bleed
+    );
+    let describe = |rect: &Rect| {
+        format!("at {},{} size {}x{}", rect.x, rect.y, rect.width, rect.height)
+    };
+    for (name, rect) in placed_elements(label) {
+        eprintln!("  {} {}", name, describe(rect));
+    }
+    for line in &label.text {
+        eprintln!("  line '{}' {}", line.text, describe(&text::line_bounds(line)));
+    }
+}
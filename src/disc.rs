@@ -0,0 +1,55 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+// Blank MiniDiscs whose label sizes are known. The recess for the label is the same on every
+// disc, but the area a sticker can safely cover varies a few mm between brands, because of how
+// far their printed logos and the shutter reach onto it
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Disc {
+    /// Sony 74 minute blanks
+    #[value(name = "sony-74")]
+    Sony74,
+    /// Sony Color Collection 80 minute blanks, the default
+    #[value(name = "sony-80")]
+    Sony80,
+    /// TDK 80 minute blanks
+    #[value(name = "tdk-80")]
+    Tdk80,
+    /// Maxell 74 minute blanks
+    #[value(name = "maxell-74")]
+    Maxell74,
+    /// Maxell 80 minute blanks
+    #[value(name = "maxell-80")]
+    Maxell80,
+    /// Sony Hi-MD 1GB blanks
+    #[value(name = "hi-md")]
+    HiMd,
+}
+
+impl Disc {
+    // The width and height of a face label for the disc in mm
+    pub fn label_size(self) -> (f32, f32) {
+        match self {
+            Disc::Sony74 | Disc::Sony80 => (36.0, 50.0),
+            Disc::Tdk80 => (35.0, 48.0),
+            Disc::Maxell74 | Disc::Maxell80 => (36.0, 48.0),
+            Disc::HiMd => (34.0, 47.0),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::env;
+use std::error::Error;
+
+use serde_derive::Deserialize;
+
+use crate::fetch::{self, FetchedLabel, FetchedTrack, USER_AGENT};
+
+const API: &str = "https://api.discogs.com";
+
+// Personal access token for the Discogs API. Releases can be looked up without one, but images
+// can only be downloaded with it
+const TOKEN_VAR: &str = "DISCOGS_TOKEN";
+
+#[derive(Deserialize)]
+struct Release {
+    title: String,
+    #[serde(default)]
+    year: u32,
+    #[serde(default)]
+    artists: Vec<Artist>,
+    #[serde(default)]
+    labels: Vec<RecordLabel>,
+    #[serde(default)]
+    images: Vec<Image>,
+    #[serde(default)]
+    tracklist: Vec<ReleaseTrack>,
+}
+
+#[derive(Deserialize)]
+struct Artist {
+    name: String,
+    #[serde(default)]
+    join: String,
+}
+
+#[derive(Deserialize)]
+struct RecordLabel {
+    name: String,
+    catno: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Image {
+    #[serde(rename = "type")]
+    kind: String,
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct ReleaseTrack {
+    #[serde(rename = "type_")]
+    kind: String,
+    title: String,
+    #[serde(default)]
+    duration: String,
+}
+
+// Releases can be given by ID, eg. `249504` or `r249504`, or by their page on the site, eg.
+// `https://www.discogs.com/release/249504-Rick-Astley-Never-Gonna-Give-You-Up`
+fn release_id(release: &str) -> Option<String> {
+    let id = match release.rsplit_once("/release/") {
+        Some((_, path)) => path,
+        None => release.trim().trim_start_matches(['r', '[']),
+    };
+    let digits: String = id.chars().take_while(char::is_ascii_digit).collect();
+    (!digits.is_empty()).then_some(digits)
+}
+
+// Discogs tells apart artists with the same name by numbering them, eg. "Nirvana (2)"
+fn artist_name(name: &str) -> &str {
+    match name.rsplit_once(" (") {
+        Some((name, number)) if number.trim_end_matches(')').parse::<u32>().is_ok() => name,
+        _ => name,
+    }
+}
+
+// Look up a release on Discogs, along with its record label, catalog number and primary image
+pub fn fetch_release(release: &str) -> Result<FetchedLabel, Box<dyn Error>> {
+    let id = release_id(release)
+        .ok_or(format!("'{}' isn't a Discogs release or release ID", release))?;
+    let token = env::var(TOKEN_VAR).ok();
+    let authorization = token.as_ref().map(|token| format!("Discogs token={}", token));
+
+    let mut request = ureq::get(format!("{}/releases/{}", API, id)).header("User-Agent", USER_AGENT);
+    if let Some(authorization) = &authorization {
+        request = request.header("Authorization", authorization);
+    }
+    let release: Release = request.call()?.body_mut().read_json()?;
+
+    let image = release.images.iter().find(|image| image.kind == "primary")
+        .or(release.images.first());
+    let cover = match (image, &authorization) {
+        (Some(image), Some(authorization)) => {
+            let headers = [("Authorization", authorization.as_str())];
+            fetch::download_cover(&image.uri, &format!("discogs-{}.jpg", id), &headers)
+                .unwrap_or_else(|e| {
+                    eprintln!("warning: couldn't download the cover: {}", e);
+                    None
+                })
+        },
+        (Some(_), None) => {
+            eprintln!("warning: set {} to download the cover from Discogs", TOKEN_VAR);
+            None
+        },
+        (None, _) => {
+            eprintln!("warning: Discogs has no images for this release");
+            None
+        },
+    };
+
+    let label = release.labels.first();
+    Ok(FetchedLabel {
+        artist: release.artists.iter()
+            .map(|artist| match artist.join.as_str() {
+                "" => artist_name(&artist.name).to_string(),
+                "," => format!("{}, ", artist_name(&artist.name)),
+                join => format!("{} {} ", artist_name(&artist.name), join),
+            })
+            .collect(),
+        release_year: (release.year != 0).then(|| release.year.to_string()),
+        record_label: label.map(|label| artist_name(&label.name).to_string()),
+        catalog_number: label.and_then(|label| label.catno.clone())
+            .filter(|catno| catno != "none"),
+        cover: cover.map(|path| path.to_string_lossy().to_string()),
+        tracks: release.tracklist.iter()
+            .filter(|track| track.kind == "track")
+            .map(|track| FetchedTrack {
+                title: track.title.clone(),
+                duration: Some(track.duration.clone()).filter(|duration| !duration.is_empty()),
+            })
+            .collect(),
+        title: release.title,
+    })
+}
@@ -0,0 +1,93 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{Rgb, RgbImage};
+
+use crate::color;
+
+// Thresholds for ordered dithering, tiled across the sheet
+pub const BAYER: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+// How shades of gray are turned into black and white for printers that can only do one or the
+// other. Error diffusion keeps the most detail in photos, ordered dithering gives an even pattern
+// that survives a coarse thermal head, and a plain threshold suits labels that are all text.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Dither {
+    #[default]
+    FloydSteinberg,
+    Ordered,
+    Threshold,
+}
+
+// Spread the difference between each pixel and the black or white it's printed as over the
+// pixels to its right and below that haven't been printed yet
+fn floyd_steinberg(gray: &mut [f32], width: usize) {
+    let height = gray.len() / width;
+    for y in 0..height {
+        for x in 0..width {
+            let old = gray[y * width + x];
+            let new = if old < 0.5 { 0.0 } else { 1.0 };
+            let error = old - new;
+            gray[y * width + x] = new;
+
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    gray[(y + dy) * width + nx as usize] += error * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+}
+
+impl Dither {
+    // Convert a sheet to pure black and white
+    pub fn apply(self, image: &RgbImage) -> RgbImage {
+        let width = image.width() as usize;
+        let mut gray: Vec<f32> = image.pixels().map(|pixel| color::luminance(*pixel)).collect();
+
+        match self {
+            Dither::FloydSteinberg => floyd_steinberg(&mut gray, width),
+            Dither::Ordered => {
+                for (i, shade) in gray.iter_mut().enumerate() {
+                    let threshold = (BAYER[i / width % 4][i % width % 4] as f32 + 0.5) / 16.0;
+                    *shade = if *shade < threshold { 0.0 } else { 1.0 };
+                }
+            },
+            Dither::Threshold => {
+                for shade in gray.iter_mut() {
+                    *shade = if *shade < 0.5 { 0.0 } else { 1.0 };
+                }
+            },
+        }
+
+        RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            Rgb([if gray[y as usize * width + x as usize] < 0.5 { 0 } else { 255 }; 3])
+        })
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use imageproc::image::RgbImage;
+
+use crate::dither::Dither;
+use crate::thermal;
+
+// LabelWriters print 300 dots to the inch with a head 672 dots across, which is 56 mm
+const DOTS_PER_MM: f32 = 300.0 / 25.4;
+const HEAD_DOTS: u32 = 672;
+
+const ESC: u8 = 0x1b;
+// Starts each line of dots
+const SYN: u8 = 0x16;
+
+// Turn a sheet into a print job for a DYMO LabelWriter, like the LabelWriter 450 or 4XL in its
+// narrow mode. The sheet is scaled to the printer's 300 dpi, turned on its side if that's the
+// only way it fits across the head, and dithered to black and white. The job prints a single
+// label the size of the sheet, starting at the top of a die-cut label and feeding out to the
+// start of the next, and can be sent straight to the printer with `lp -o raw`.
+pub fn raster(sheet: &RgbImage, dpmm: f32, dither: Dither) -> Result<Vec<u8>, Box<dyn Error>> {
+    let head = format!("a LabelWriter's {} mm head", (HEAD_DOTS as f32 / DOTS_PER_MM).round());
+    let dots = thermal::dots(sheet, dpmm, DOTS_PER_MM, HEAD_DOTS, &head, dither)?;
+    let bytes_per_line = dots.width().div_ceil(8) as u8;
+    // The label length is sent as a 16 bit count of lines
+    let length = u16::try_from(dots.height()).map_err(|_| format!(
+        "a {:.0} mm long sheet is too long for a LabelWriter, which prints up to {:.0} mm at once",
+        dots.height() as f32 / DOTS_PER_MM, u16::MAX as f32 / DOTS_PER_MM
+    ))?;
+
+    // Reset, then print lines of dots from the edge of the head at 300x300 dpi and normal
+    // density, on a label as long as the sheet
+    let mut job = vec![ESC, b'@', ESC, b'B', 0, ESC, b'D', bytes_per_line, ESC, b'L'];
+    job.extend(length.to_be_bytes());
+    job.extend([ESC, b'h', ESC, b'e']);
+
+    for row in dots.rows() {
+        let line: Vec<bool> = row.map(thermal::is_black).collect();
+        job.push(SYN);
+        job.extend(thermal::pack(&line));
+    }
+    // Feed to the top of the next label
+    job.extend([ESC, b'E']);
+    Ok(job)
+}
@@ -0,0 +1,99 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::io;
+
+use imageproc::image::ImageError;
+
+// Exit codes for each kind of error, so that scripts can tell them apart. Anything else exits
+// with 1, and clap exits with 2 when the command line itself is wrong.
+const OTHER: u8 = 1;
+const CONFIG: u8 = 3;
+const MISSING_FILE: u8 = 4;
+const DECODE: u8 = 5;
+const RENDER: u8 = 6;
+
+// Listed at the end of `--help`
+pub const EXIT_CODES: &str = "Exit codes:
+  0  the labels were made
+  1  anything not listed below, like failing to save or print
+  2  the command line can't be understood
+  3  a layout, theme or option can't be used as given
+  4  a cover, layout or other file that's needed is missing or can't be read
+  5  a cover or other image can't be decoded
+  6  a label can't be drawn as it's laid out, eg. its text doesn't fit";
+
+// What went wrong, by the kinds that have exit codes of their own
+#[derive(thiserror::Error, Debug)]
+pub enum LabelError {
+    /// A layout, theme or option that can't be used as given
+    #[error("{0}")]
+    Config(String),
+    /// A file that's needed but isn't there or can't be read, described along with its path
+    #[error("can't read {what}: {source}")]
+    MissingFile { what: String, source: io::Error },
+    /// An image that can't be decoded
+    #[error("can't decode {what}: {source}")]
+    Decode { what: String, source: ImageError },
+    /// A label that can't be drawn as it's laid out
+    #[error("{0}")]
+    Render(String),
+}
+
+// The exit code for an error. Errors from libraries are sorted by their type where that says what
+// kind they are.
+pub fn exit_code(error: &(dyn Error + 'static)) -> u8 {
+    if let Some(error) = error.downcast_ref::<LabelError>() {
+        return match error {
+            LabelError::Config(_) => CONFIG,
+            LabelError::MissingFile { .. } => MISSING_FILE,
+            LabelError::Decode { .. } => DECODE,
+            LabelError::Render(_) => RENDER,
+        };
+    }
+    if error.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::NotFound) {
+        MISSING_FILE
+    } else if error.is::<ImageError>() {
+        DECODE
+    } else if error.is::<toml::de::Error>() || error.is::<serde_json::Error>()
+        || error.is::<serde_yaml::Error>() {
+        CONFIG
+    } else {
+        OTHER
+    }
+}
+
+// Treat an error that isn't of any kind yet as the given kind, by its message
+fn classify(error: Box<dyn Error>, kind: fn(String) -> LabelError) -> Box<dyn Error> {
+    match exit_code(&*error) {
+        OTHER => Box::new(kind(error.to_string())),
+        _ => error,
+    }
+}
+
+// For errors in reading a layout or working out the settings it's drawn with
+pub fn config(error: Box<dyn Error>) -> Box<dyn Error> {
+    classify(error, LabelError::Config)
+}
+
+// For errors in drawing a label
+pub fn render(error: Box<dyn Error>) -> Box<dyn Error> {
+    classify(error, LabelError::Render)
+}
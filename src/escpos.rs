@@ -0,0 +1,70 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use imageproc::image::RgbImage;
+
+use crate::dither::Dither;
+use crate::thermal;
+
+// Receipt printers print 203 dots to the inch
+const DOTS_PER_MM: f32 = 203.0 / 25.4;
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+
+// Dots that have been dithered to black and white as `GS v 0` raster bit images. Printers only
+// take so many lines in one image, so tall ones are sent as a strip of images 255 lines high.
+pub fn raster_image(dots: &RgbImage) -> Vec<u8> {
+    let bytes_per_line = dots.width().div_ceil(8) as u16;
+    let lines: Vec<Vec<u8>> = dots.rows()
+        .map(|row| thermal::pack(&row.map(thermal::is_black).collect::<Vec<_>>()))
+        .collect();
+
+    let mut image = Vec::new();
+    for block in lines.chunks(255) {
+        image.extend([GS, b'v', b'0', 0]);
+        image.extend(bytes_per_line.to_le_bytes());
+        image.extend((block.len() as u16).to_le_bytes());
+        image.extend(block.concat());
+    }
+    image
+}
+
+// Turn a sheet into an ESC/POS job for a receipt or label printer `width` mm across, which can be
+// sent to it over serial or USB, eg. by copying it to `/dev/usb/lp0`. The sheet is scaled to
+// 203 dpi, turned on its side if that's the only way it fits, dithered to black and white, and
+// printed centred on the paper before it's fed out and cut.
+pub fn raster(
+    sheet: &RgbImage,
+    dpmm: f32,
+    width: f32,
+    dither: Dither
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let paper = format!("{} mm wide paper", width);
+    let head = (width * DOTS_PER_MM).round() as u32;
+    let dots = thermal::dots(sheet, dpmm, DOTS_PER_MM, head, &paper, dither)?;
+
+    let mut job = vec![ESC, b'@', ESC, b'a', 1];
+    job.extend(raster_image(&dots));
+    // Feed the strip past the cutter and cut it, leaving a little holding it on
+    job.extend([ESC, b'd', 4, GS, b'V', 66, 0]);
+    Ok(job)
+}
@@ -0,0 +1,130 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+use crate::{discogs, musicbrainz};
+use crate::{FetchArgs, LayoutFormat};
+
+// Online databases ask every client to identify itself
+pub const USER_AGENT: &str = concat!(
+    "mdlabelgen/", env!("CARGO_PKG_VERSION"), " ( https://github.com/eightbitraptor/mdlabelgen )"
+);
+
+// A label written out to a layout, in the same shape as the labels a layout is read into
+#[derive(Serialize)]
+struct FetchedLayout {
+    labels: Vec<FetchedLabel>,
+}
+
+#[derive(Serialize)]
+pub struct FetchedLabel {
+    pub title: String,
+    pub artist: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_year: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub catalog_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<FetchedTrack>,
+}
+
+#[derive(Serialize)]
+pub struct FetchedTrack {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+}
+
+// Download a cover image into the cache as `name`, unless it's already there. Returns the path to
+// the cover, or nothing if there's no image at `url`.
+pub fn download_cover(
+    url: &str,
+    name: &str,
+    headers: &[(&str, &str)]
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let dir = dirs::cache_dir().ok_or("can't find a cache directory")?
+        .join("mdlabelgen").join("covers");
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(Some(path));
+    }
+
+    let mut request = ureq::get(url).header("User-Agent", USER_AGENT);
+    for (key, value) in headers {
+        request = request.header(*key, *value);
+    }
+    let mut response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::StatusCode(404)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, response.body_mut().read_to_vec()?)?;
+    Ok(Some(path))
+}
+
+// Look up a release online and add a label for it to the end of a layout file. The layout is
+// created if it doesn't exist.
+pub fn fetch(args: &FetchArgs) -> Result<(), Box<dyn Error>> {
+    if LayoutFormat::from_path(&args.layout) != LayoutFormat::Toml {
+        return Err("fetch can only add labels to TOML layouts".into());
+    }
+
+    let label = match (&args.discogs, &args.mbid, &args.artist, &args.album) {
+        (Some(release), _, _, _) => discogs::fetch_release(release)?,
+        (None, Some(id), _, _) => musicbrainz::fetch_release(id)?,
+        (None, None, Some(artist), Some(album)) => {
+            musicbrainz::fetch_release(&musicbrainz::search(artist, album)?)?
+        },
+        _ => return Err(
+            "fetch needs --discogs, --mbid or both --artist and --album".into()
+        ),
+    };
+
+    append_label(Path::new(&args.layout), label)
+}
+
+// A label as a `[[labels]]` table, ready to be added to a layout
+pub fn label_toml(label: FetchedLabel) -> Result<String, Box<dyn Error>> {
+    Ok(toml::to_string(&FetchedLayout { labels: vec![label] })?)
+}
+
+// Add a label to the end of a TOML layout, creating the layout if it doesn't exist. Appending
+// another [[labels]] table adds to whatever labels the layout already has.
+pub fn append_label(path: &Path, label: FetchedLabel) -> Result<(), Box<dyn Error>> {
+    let summary = format!("'{}' by {}", label.title, label.artist);
+    let toml = label_toml(label)?;
+    let separator = if path.exists() { "\n" } else { "" };
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    write!(file, "{}{}", separator, toml)?;
+
+    println!("Added {} to {}", summary, path.display());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{Rgb, RgbImage};
+use schemars::JsonSchema;
+use serde_derive::Deserialize;
+
+use crate::color::Color;
+use crate::layout::Rect;
+
+// A background that fades from one color to another, eg.
+//
+//   gradient = { from = "#1b1464", to = "#ff2a95", angle = 135 }
+//
+// Angles follow CSS, so 0 runs up the label, 90 across it to the right, and the default of 180
+// down it. Radial gradients start at the center and reach `to` at the corners.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct Gradient {
+    #[serde(default)]
+    pub shape: Shape,
+    pub from: Color,
+    pub to: Color,
+    /// Direction of a linear gradient in degrees
+    #[serde(default = "default_angle")]
+    pub angle: f32,
+    /// Whether the gradient spans the whole label or just the part the text goes in
+    #[serde(default)]
+    pub area: FillArea,
+}
+
+fn default_angle() -> f32 {
+    180.0
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    #[default]
+    Linear,
+    Radial,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FillArea {
+    #[default]
+    Label,
+    Text,
+}
+
+fn mix(from: Rgb<u8>, to: Rgb<u8>, t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0);
+    Rgb([0, 1, 2].map(|c| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8))
+}
+
+// Paint a gradient over part of an image. A linear gradient runs along a line through the middle
+// of the area at its angle, long enough that the corners are exactly `from` and `to`.
+pub fn fill_gradient(image: &mut RgbImage, area: Rect, gradient: &Gradient) {
+    let (Color(from), Color(to)) = (gradient.from, gradient.to);
+    let (width, height) = (area.width as f32, area.height as f32);
+    let (center_x, center_y) = (area.x as f32 + width / 2.0, area.y as f32 + height / 2.0);
+
+    let (sin, cos) = gradient.angle.to_radians().sin_cos();
+    let length = (width * sin).abs() + (height * cos).abs();
+    let radius = (width * width + height * height).sqrt() / 2.0;
+
+    let x_range = area.x.max(0) as u32..(area.right().max(0) as u32).min(image.width());
+    let y_range = area.y.max(0) as u32..(area.bottom().max(0) as u32).min(image.height());
+    for y in y_range {
+        for x in x_range.clone() {
+            let (dx, dy) = (x as f32 + 0.5 - center_x, y as f32 + 0.5 - center_y);
+            let t = match gradient.shape {
+                Shape::Linear if length > 0.0 => (dx * sin - dy * cos) / length + 0.5,
+                Shape::Linear => 0.0,
+                Shape::Radial if radius > 0.0 => (dx * dx + dy * dy).sqrt() / radius,
+                Shape::Radial => 0.0,
+            };
+            image.put_pixel(x, y, mix(from, to, t));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use ab_glyph::{Font, FontArc};
+use fontdb::{Database, Family, Query, Stretch, Style, Weight};
+
+// A font to draw label text with, along with enough about it to refer to it by name in vector
+// output. Characters the font doesn't have are taken from the first of its fallbacks that does.
+// The raw font data is kept alongside the parsed font for the shaper to work from.
+#[derive(Clone)]
+pub struct LabelFont {
+    pub font: FontArc,
+    pub data: Arc<[u8]>,
+    pub index: u32,
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub fallbacks: Vec<LabelFont>,
+}
+
+impl LabelFont {
+    // Liberation Sans Bold, which is compiled into the binary and always available
+    pub fn bundled() -> LabelFont {
+        let data = include_bytes!("../res/liberation_sans/LiberationSans-Bold.ttf");
+        let font = FontArc::try_from_slice(data).expect("bundled font is valid");
+
+        LabelFont {
+            font,
+            data: Arc::from(&data[..]),
+            index: 0,
+            family: "Liberation Sans".to_string(),
+            weight: 700,
+            italic: false,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    // Load a font given either as a path to a font file or the name of an installed font
+    pub fn load(name: &str) -> Result<LabelFont, Box<dyn Error>> {
+        if Path::new(name).is_file() {
+            LabelFont::from_file(name)
+        } else {
+            Ok(LabelFont::system(name).ok_or(format!("no installed font matches '{}'", name))?)
+        }
+    }
+
+    pub fn with_fallbacks(mut self, fallbacks: &[LabelFont]) -> LabelFont {
+        self.fallbacks = fallbacks.to_vec();
+        self
+    }
+
+    // The font to draw a character with. If nothing in the fallback chain has it, the primary
+    // font's missing glyph is used.
+    pub fn for_char(&self, c: char) -> &LabelFont {
+        std::iter::once(self)
+            .chain(&self.fallbacks)
+            .find(|font| font.font.glyph_id(c).0 != 0)
+            .unwrap_or(self)
+    }
+
+    // Load a TrueType or OpenType font file
+    pub fn from_file(path: &str) -> Result<LabelFont, Box<dyn Error>> {
+        let data = fs::read(path)
+            .map_err(|e| format!("can't read font '{}': {}", path, e))?;
+
+        let mut db = Database::new();
+        db.load_font_data(data.clone());
+        let face = db.faces().next().ok_or(format!("'{}' is not a font", path))?;
+
+        Ok(LabelFont {
+            font: FontArc::try_from_vec(data.clone())?,
+            data: Arc::from(data),
+            index: 0,
+            family: face.families.first().map_or(String::new(), |(name, _)| name.clone()),
+            weight: face.weight.0,
+            italic: face.style != Style::Normal,
+            fallbacks: Vec::new(),
+        })
+    }
+
+    // Find an installed font by its full name, eg. "Noto Sans JP Bold". The weight and style are
+    // taken from the end of the name, defaulting to a regular upright face.
+    pub fn system(name: &str) -> Option<LabelFont> {
+        let db = system_fonts();
+
+        // Some families have a weight in their name (eg. "Arial Black"), so try the whole name
+        // as a family before picking it apart
+        let (family, weight, stretch, style) = parse_font_name(name);
+        let id = db.query(&Query { families: &[Family::Name(name.trim())], ..Query::default() })
+            .or_else(|| db.query(&Query {
+                families: &[Family::Name(&family)],
+                weight,
+                stretch,
+                style,
+            }))?;
+
+        let face = db.face(id)?;
+        let family = face.families.first().map_or(family.clone(), |(name, _)| name.clone());
+        let weight = face.weight.0;
+        let italic = face.style != Style::Normal;
+
+        let (data, index) = db.with_face_data(id, |data, index| (data.to_vec(), index))?;
+        let font = ab_glyph::FontVec::try_from_vec_and_index(data.clone(), index).ok()?;
+
+        Some(LabelFont {
+            font: FontArc::new(font),
+            data: Arc::from(data),
+            index,
+            family,
+            weight,
+            italic,
+            fallbacks: Vec::new(),
+        })
+    }
+}
+
+// The installed fonts, which are looked up the first time they're needed and kept after that
+fn system_fonts() -> &'static Database {
+    static FONTS: OnceLock<Database> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut db = Database::new();
+        db.load_system_fonts();
+        db
+    })
+}
+
+fn weight_from_name(word: &str) -> Option<Weight> {
+    match word {
+        "thin" | "hairline" => Some(Weight::THIN),
+        "extralight" | "ultralight" => Some(Weight::EXTRA_LIGHT),
+        "light" => Some(Weight::LIGHT),
+        "regular" | "normal" | "book" => Some(Weight::NORMAL),
+        "medium" => Some(Weight::MEDIUM),
+        "semibold" | "demibold" => Some(Weight::SEMIBOLD),
+        "bold" => Some(Weight::BOLD),
+        "extrabold" | "ultrabold" | "heavy" => Some(Weight::EXTRA_BOLD),
+        "black" => Some(Weight::BLACK),
+        _ => None,
+    }
+}
+
+fn stretch_from_name(word: &str) -> Option<Stretch> {
+    match word {
+        "ultracondensed" => Some(Stretch::UltraCondensed),
+        "extracondensed" => Some(Stretch::ExtraCondensed),
+        "condensed" => Some(Stretch::Condensed),
+        "semicondensed" => Some(Stretch::SemiCondensed),
+        "semiexpanded" => Some(Stretch::SemiExpanded),
+        "expanded" => Some(Stretch::Expanded),
+        "extraexpanded" => Some(Stretch::ExtraExpanded),
+        "ultraexpanded" => Some(Stretch::UltraExpanded),
+        _ => None,
+    }
+}
+
+// Split a full font name into its family, weight, stretch and style, eg.
+// "Noto Sans JP Bold Italic" becomes ("Noto Sans JP", BOLD, Normal, Italic)
+fn parse_font_name(name: &str) -> (String, Weight, Stretch, Style) {
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    let mut weight = Weight::NORMAL;
+    let mut stretch = Stretch::Normal;
+    let mut style = Style::Normal;
+
+    while let Some(last) = words.pop() {
+        let mut word = last.to_lowercase();
+
+        // Join up split descriptions like "Extra Bold" or "Semi Condensed"
+        if let Some(prefix) = words.last().map(|w| w.to_lowercase()) {
+            let joined = prefix + &word;
+            if weight_from_name(&joined).is_some() || stretch_from_name(&joined).is_some() {
+                word = joined;
+                words.pop();
+            }
+        }
+
+        if word == "italic" {
+            style = Style::Italic;
+        } else if word == "oblique" {
+            style = Style::Oblique;
+        } else if let Some(w) = weight_from_name(&word) {
+            weight = w;
+        } else if let Some(s) = stretch_from_name(&word) {
+            stretch = s;
+        } else {
+            words.push(last);
+            break;
+        }
+    }
+
+    (words.join(" "), weight, stretch, style)
+}
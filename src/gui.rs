@@ -0,0 +1,306 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use eframe::egui;
+
+use crate::layout::Rect;
+use crate::{GuiArgs, LayoutFormat, RenderArgs, RenderedLabel};
+
+// Labels are drawn at a lower resolution than they're printed at, so they keep up with typing
+const PREVIEW_DPI: u32 = 150;
+
+// The details of a label that can be edited, as they're named in the layout
+const FIELDS: [(&str, &str); 4] =
+    [("Artist", "artist"), ("Title", "title"), ("Year", "release_year"), ("Cover", "cover")];
+
+// Elements that can be dragged around the preview, in the order they're picked up when they
+// overlap. The cover comes last since everything else tends to sit on top of it.
+const DRAGGABLE: [&str; 7] = ["title", "artist", "year", "footer", "logo", "qr-code", "cover"];
+
+// A label as it's shown in the editor, along with the scale it was drawn at
+struct Preview {
+    texture: egui::TextureHandle,
+    label: RenderedLabel,
+    dpmm: f32,
+}
+
+// An element being dragged, where it started and how far it's been moved in label pixels
+struct Drag {
+    name: String,
+    start: Rect,
+    moved: egui::Vec2,
+}
+
+struct Editor<'a> {
+    render: &'a RenderArgs,
+    path: String,
+    // The layout as it will be saved, which keeps any settings the editor doesn't know about
+    document: toml::Table,
+    selected: usize,
+    preview: Option<Preview>,
+    problem: Option<String>,
+    stale: bool,
+    drag: Option<Drag>,
+    status: String,
+}
+
+fn placeholder_label() -> toml::Value {
+    toml::Value::Table(toml::Table::from_iter([
+        ("artist".to_string(), toml::Value::from("Artist")),
+        ("title".to_string(), toml::Value::from("Title")),
+    ]))
+}
+
+impl Editor<'_> {
+    fn labels(&mut self) -> &mut Vec<toml::Value> {
+        let labels = self.document.entry("labels").or_insert(toml::Value::Array(Vec::new()));
+        if !labels.is_array() {
+            *labels = toml::Value::Array(Vec::new());
+        }
+        labels.as_array_mut().expect("labels was just made an array")
+    }
+
+    fn label(&mut self) -> &mut toml::Table {
+        let selected = self.selected;
+        let labels = self.labels();
+        if labels.is_empty() {
+            labels.push(placeholder_label());
+        }
+        let last = labels.len() - 1;
+        let label = &mut labels[selected.min(last)];
+        if !label.is_table() {
+            *label = placeholder_label();
+        }
+        label.as_table_mut().expect("the label was just made a table")
+    }
+
+    // Draw the selected label as it would be printed
+    fn draw(&mut self) -> Result<(RenderedLabel, f32), Box<dyn Error>> {
+        let layout = toml::to_string(&self.document)?;
+        let mut label_config = crate::parse_config(&layout, LayoutFormat::Toml)?;
+        label_config.dpi = Some(PREVIEW_DPI);
+        let theme = crate::load_theme(self.render, &label_config)?;
+        let context = crate::render_context(self.render, &label_config, &theme)?;
+
+        let label_info = label_config.labels.get(self.selected).ok_or("there are no labels")?;
+        let label_type = label_info.label_type.unwrap_or_default();
+        let label = crate::render_label(label_info, label_type, &context)?;
+        Ok((label, context.metrics.dpmm))
+    }
+
+    fn refresh(&mut self, ctx: &egui::Context) {
+        self.stale = false;
+        match self.draw() {
+            Ok((label, dpmm)) => {
+                let image = crate::overlay_text(&label.art, &label.text);
+                let size = [image.width() as usize, image.height() as usize];
+                let texture = ctx.load_texture(
+                    "label", egui::ColorImage::from_rgb(size, image.as_raw()), Default::default()
+                );
+                self.preview = Some(Preview { texture, label, dpmm });
+                self.problem = None;
+            },
+            Err(e) => self.problem = Some(e.to_string()),
+        }
+    }
+
+    fn save(&mut self) {
+        self.status = match toml::to_string_pretty(&self.document) {
+            Ok(layout) => match fs::write(&self.path, layout) {
+                Ok(()) => format!("Saved {}", self.path),
+                Err(e) => format!("Can't save {}: {}", self.path, e),
+            },
+            Err(e) => format!("Can't save {}: {}", self.path, e),
+        };
+    }
+
+    // Put a dragged element where it was dropped, measured from the top left of the label
+    fn place(&mut self, drag: Drag, dpmm: f32) {
+        let offset = |px: f32| ((px / dpmm * 10.0).round() / 10.0) as f64;
+        let placement = toml::Table::from_iter([
+            ("anchor".to_string(), toml::Value::from("top-left")),
+            ("offset".to_string(), toml::Value::from(vec![
+                offset(drag.start.x as f32 + drag.moved.x),
+                offset(drag.start.y as f32 + drag.moved.y),
+            ])),
+        ]);
+        let layout = self.document.entry("layout")
+            .or_insert(toml::Value::Table(Default::default()));
+        if let Some(layout) = layout.as_table_mut() {
+            layout.insert(drag.name, toml::Value::Table(placement));
+        }
+        self.stale = true;
+    }
+
+    fn side_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Labels");
+        let titles: Vec<String> = self.labels().iter()
+            .map(|label| label.get("title").and_then(toml::Value::as_str).unwrap_or("").to_string())
+            .collect();
+        for (n, title) in titles.iter().enumerate() {
+            if ui.selectable_label(n == self.selected, format!("{}. {}", n + 1, title)).clicked() {
+                self.selected = n;
+                self.stale = true;
+            }
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                self.labels().push(placeholder_label());
+                self.selected = titles.len();
+                self.stale = true;
+            }
+            if ui.add_enabled(titles.len() > 1, egui::Button::new("Remove")).clicked() {
+                let selected = self.selected;
+                self.labels().remove(selected);
+                self.selected = selected.saturating_sub(1);
+                self.stale = true;
+            }
+        });
+
+        ui.separator();
+        egui::Grid::new("fields").num_columns(2).show(ui, |ui| {
+            for (name, key) in FIELDS {
+                let label = self.label();
+                let mut value = label.get(key).and_then(toml::Value::as_str)
+                    .unwrap_or("").to_string();
+                ui.label(name);
+                if ui.text_edit_singleline(&mut value).changed() {
+                    if value.is_empty() && key != "artist" && key != "title" {
+                        label.remove(key);
+                    } else {
+                        label.insert(key.to_string(), toml::Value::from(value));
+                    }
+                    self.stale = true;
+                }
+                ui.end_row();
+            }
+        });
+        ui.label("Drop an image on the window to use it as the cover. Drag the text, logo and \
+            cover around the label to move them.");
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                self.save();
+            }
+            if ui.button("Reset layout").clicked() {
+                self.document.remove("layout");
+                self.stale = true;
+            }
+        });
+        ui.label(format!("{}\n{}", self.path, self.status));
+    }
+
+    fn label_view(&mut self, ui: &mut egui::Ui) {
+        if let Some(problem) = &self.problem {
+            ui.colored_label(egui::Color32::RED, problem);
+        }
+        let Some(preview) = &self.preview else { return };
+
+        let image_size = preview.texture.size_vec2();
+        let available = ui.available_size();
+        let scale = (available.x / image_size.x).min(available.y / image_size.y);
+        let (rect, response) = ui.allocate_exact_size(image_size * scale, egui::Sense::drag());
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        ui.painter().image(preview.texture.id(), rect, uv, egui::Color32::WHITE);
+
+        // Positions on the label are in label pixels, and on screen in points
+        let to_screen = |label_rect: Rect, moved: egui::Vec2| egui::Rect::from_min_size(
+            rect.min + (egui::vec2(label_rect.x as f32, label_rect.y as f32) + moved) * scale,
+            egui::vec2(label_rect.width as f32, label_rect.height as f32) * scale,
+        );
+
+        if response.drag_started() {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let at = (pointer - rect.min) / scale;
+                self.drag = DRAGGABLE.iter()
+                    .filter_map(|name| preview.label.elements.get(*name).map(|rect| (name, rect)))
+                    .find(|(_, element)| {
+                        at.x >= element.x as f32 && at.x < element.right() as f32
+                            && at.y >= element.y as f32 && at.y < element.bottom() as f32
+                    })
+                    .map(|(name, element)| Drag {
+                        name: name.to_string(), start: *element, moved: egui::Vec2::ZERO
+                    });
+            }
+        }
+        if let Some(drag) = &mut self.drag {
+            drag.moved += response.drag_delta() / scale;
+            let outline = egui::Stroke::new(2.0, egui::Color32::from_rgb(0, 160, 255));
+            ui.painter().rect_stroke(to_screen(drag.start, drag.moved), 0.0, outline,
+                egui::StrokeKind::Outside);
+        }
+        if response.drag_stopped() {
+            let dpmm = preview.dpmm;
+            if let Some(drag) = self.drag.take() {
+                self.place(drag, dpmm);
+            }
+        }
+    }
+}
+
+impl eframe::App for Editor<'_> {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let dropped = ui.ctx().input(|input| input.raw.dropped_files.clone());
+        if let Some(file) = dropped.first() {
+            let cover = file.path().to_string_lossy().to_string();
+            self.label().insert("cover".to_string(), toml::Value::from(cover));
+            self.stale = true;
+        }
+        if self.stale && self.drag.is_none() {
+            self.refresh(ui.ctx());
+        }
+
+        egui::Panel::left("labels").resizable(true).show(ui, |ui| self.side_panel(ui));
+        egui::CentralPanel::default_margins().show(ui, |ui| self.label_view(ui));
+    }
+}
+
+// Open the editor on a layout, which is started afresh if it doesn't exist yet
+pub fn run(args: &GuiArgs) -> Result<(), Box<dyn Error>> {
+    let document: toml::Table = if Path::new(&args.layout).exists() {
+        toml::from_str(&crate::read_layout(&args.layout)?)?
+    } else {
+        toml::Table::from_iter([
+            ("labels".to_string(), toml::Value::Array(vec![placeholder_label()])),
+        ])
+    };
+    let editor = Editor {
+        render: &args.render,
+        path: args.layout.clone(),
+        document,
+        selected: 0,
+        preview: None,
+        problem: None,
+        stale: true,
+        drag: None,
+        status: String::new(),
+    };
+
+    eframe::run_native(
+        &format!("mdlabelgen - {}", args.layout),
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(editor))),
+    ).map_err(|e| format!("can't open the editor: {}", e))?;
+    Ok(())
+}
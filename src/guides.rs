@@ -0,0 +1,109 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{Rgb, RgbImage};
+
+use crate::layout::Rect;
+use crate::{Metrics, Sheet};
+
+// Guides are thin gray marks, dark enough to cut along but light enough not to distract if they
+// show at the edge of a label
+pub const GUIDE_COLOR: Rgb<u8> = Rgb([128, 128, 128]);
+
+// Sizes in mm. Dashes are as long as the gaps between them, and ticks run this far out from the
+// corners of each label, or from the edge of its bleed
+const GUIDE_THICKNESS: f32 = 0.1;
+const DASH_LENGTH: f32 = 1.0;
+const TICK_LENGTH: f32 = 2.0;
+
+// Marks on a sheet showing where to cut the labels out
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum CutGuides {
+    /// Dashed lines around each label
+    Lines,
+    /// Tick marks running out from the corners of each label, in line with its edges
+    Ticks,
+}
+
+// Split a line from `start` that's `length` long into dashes, as the start and length of each
+fn dashes(start: i32, length: i32, dash: i32) -> impl Iterator<Item = (i32, i32)> {
+    (start..start + length).step_by(2 * dash as usize)
+        .map(move |from| (from, dash.min(start + length - from)))
+}
+
+// The guides for the labels on a sheet, as rectangles to fill in sheet pixels. They're drawn
+// under the labels, so they only show in the gaps between them. Guides go around where each label
+// is cut, which is inside its bleed if it has any.
+pub fn marks(sheet: &Sheet, guides: CutGuides, metrics: &Metrics) -> Vec<Rect> {
+    let thickness = metrics.px(GUIDE_THICKNESS).max(1);
+    let mut marks = Vec::new();
+
+    for (x, y, label) in &sheet.labels {
+        let (x, y) = (*x as i32 + label.bleed, *y as i32 + label.bleed);
+        let right = x + label.art.width() as i32 - 2 * label.bleed;
+        let bottom = y + label.art.height() as i32 - 2 * label.bleed;
+        match guides {
+            CutGuides::Lines => {
+                let dash = metrics.px(DASH_LENGTH).max(1);
+                let (across, down) = (right - x + 2 * thickness, bottom - y + 2 * thickness);
+                for (from, length) in dashes(x - thickness, across, dash) {
+                    for edge in [y - thickness, bottom] {
+                        marks.push(Rect { x: from, y: edge, width: length, height: thickness });
+                    }
+                }
+                for (from, length) in dashes(y - thickness, down, dash) {
+                    for edge in [x - thickness, right] {
+                        marks.push(Rect { x: edge, y: from, width: thickness, height: length });
+                    }
+                }
+            },
+            CutGuides::Ticks => {
+                let tick = metrics.px(TICK_LENGTH) + label.bleed;
+                for (corner_x, corner_y) in [(x, y), (right, y), (x, bottom), (right, bottom)] {
+                    let (out_x, out_y) = (corner_x == right, corner_y == bottom);
+                    marks.push(Rect {
+                        x: if out_x { corner_x } else { corner_x - tick },
+                        y: corner_y - if out_y { thickness } else { 0 },
+                        width: tick,
+                        height: thickness,
+                    });
+                    marks.push(Rect {
+                        x: corner_x - if out_x { thickness } else { 0 },
+                        y: if out_y { corner_y } else { corner_y - tick },
+                        width: thickness,
+                        height: tick,
+                    });
+                }
+            },
+        }
+    }
+    marks
+}
+
+// Fill in the marks on a sheet, clipped to its edges
+pub fn draw(image: &mut RgbImage, marks: &[Rect]) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    for mark in marks {
+        for y in mark.y.max(0)..mark.bottom().min(height) {
+            for x in mark.x.max(0)..mark.right().min(width) {
+                image.put_pixel(x as u32, y as u32, GUIDE_COLOR);
+            }
+        }
+    }
+}
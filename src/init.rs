@@ -0,0 +1,64 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::output;
+use crate::InitArgs;
+
+// The commented settings at the top of a new layout, which the placeholder labels follow
+const STARTER: &str = include_str!("../res/starter.toml");
+
+// A new layout, with `count` placeholder labels to fill in
+fn starter_layout(count: usize) -> String {
+    let mut layout = STARTER.to_string();
+    for n in 1..=count {
+        layout.push_str(&format!(
+            "\n[[labels]]\nartist = \"Artist\"\ntitle = \"Album {n}\"\nrelease_year = \"2024\"\n\
+            cover = \"covers/album-{n}.jpg\"\n"
+        ));
+        if n == 1 {
+            layout.push_str(
+                "# label_type = \"face\"\n# cover_fit = \"fill\"\n# tracks = [\"First Track\", \
+                { title = \"Second Track\", duration = \"4:05\" }]\n"
+            );
+        }
+    }
+    layout
+}
+
+// Write a starter layout for someone to fill in with their own labels
+pub fn init(args: &InitArgs) -> Result<(), Box<dyn Error>> {
+    if args.labels == 0 {
+        return Err("a layout needs at least one label".into());
+    }
+
+    let path = Path::new(&args.layout);
+    if path.exists() && !output::is_stdout(path) && !args.force {
+        return Err(format!(
+            "'{}' already exists, use --force to replace it", path.display()
+        ).into());
+    }
+    output::write_file(path, starter_layout(args.labels).as_bytes())?;
+    if !output::is_stdout(path) {
+        println!("Wrote a layout with {} labels to {}", args.labels, path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,228 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde_derive::Deserialize;
+
+// Where an element goes on a label, eg. `{ anchor = "below:title", offset = [0, 1.5] }`. The
+// offset moves the element on from its anchor in mm, with positive values going right and down.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct Placement {
+    pub anchor: Anchor,
+    #[serde(default)]
+    pub offset: [f32; 2],
+}
+
+// What an element is placed against, and how. Anchors are written as a position, optionally
+// followed by the element it's relative to, eg. "bottom-right" or "below:title". Without an
+// element, positions are relative to the whole label. The nine positions from "top-left" to
+// "bottom-right" put the element inside that part of what it's relative to, and "above",
+// "below", "left-of" and "right-of" put it outside, against that side.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Anchor {
+    pub position: Position,
+    pub relative_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    Above,
+    Below,
+    LeftOf,
+    RightOf,
+}
+
+const POSITIONS: [(&str, Position); 13] = [
+    ("top-left", Position::TopLeft),
+    ("top", Position::Top),
+    ("top-right", Position::TopRight),
+    ("left", Position::Left),
+    ("center", Position::Center),
+    ("right", Position::Right),
+    ("bottom-left", Position::BottomLeft),
+    ("bottom", Position::Bottom),
+    ("bottom-right", Position::BottomRight),
+    ("above", Position::Above),
+    ("below", Position::Below),
+    ("left-of", Position::LeftOf),
+    ("right-of", Position::RightOf),
+];
+
+impl Anchor {
+    pub fn new(position: Position, relative_to: Option<&str>) -> Anchor {
+        Anchor { position, relative_to: relative_to.map(str::to_string) }
+    }
+}
+
+impl JsonSchema for Anchor {
+    fn schema_name() -> Cow<'static, str> {
+        "Anchor".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let names: Vec<&str> = POSITIONS.iter().map(|(name, _)| *name).collect();
+        json_schema!({
+            "description": "A position, optionally followed by the element it's relative to",
+            "type": "string",
+            "pattern": format!("^({})(:.+)?$", names.join("|")),
+        })
+    }
+}
+
+impl TryFrom<String> for Anchor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Anchor, String> {
+        let (position, relative_to) = match value.split_once(':') {
+            Some((position, element)) => (position.trim(), Some(element.trim())),
+            None => (value.trim(), None),
+        };
+        let position = POSITIONS.iter()
+            .find(|(name, _)| *name == position)
+            .map(|(_, position)| *position)
+            .ok_or_else(|| {
+                let names: Vec<&str> = POSITIONS.iter().map(|(name, _)| *name).collect();
+                format!("'{}' isn't an anchor, use one of {}", position, names.join(", "))
+            })?;
+
+        let outside = matches!(position,
+            Position::Above | Position::Below | Position::LeftOf | Position::RightOf
+        );
+        if outside && relative_to.is_none() {
+            return Err(format!("'{}' needs an element to go next to, eg. \"{}:title\"",
+                value, value));
+        }
+        Ok(Anchor::new(position, relative_to))
+    }
+}
+
+// A rectangle on a label, in label pixels
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right()
+            && self.y < other.bottom() && other.y < self.bottom()
+    }
+}
+
+// An element to be placed, with its size and offset in pixels
+pub struct Element<'a> {
+    pub name: &'a str,
+    pub width: i32,
+    pub height: i32,
+    pub anchor: Anchor,
+    pub offset: (i32, i32),
+}
+
+fn place(
+    index: usize,
+    elements: &[Element],
+    bounds: Rect,
+    placed: &mut Vec<Option<Rect>>,
+    visiting: &mut Vec<usize>
+) -> Result<Rect, Box<dyn Error>> {
+    if let Some(rect) = placed[index] {
+        return Ok(rect);
+    }
+    let element = &elements[index];
+    if visiting.contains(&index) {
+        return Err(format!("the layout of '{}' depends on itself", element.name).into());
+    }
+
+    let target = match &element.anchor.relative_to {
+        None => bounds,
+        Some(name) => {
+            let target = elements.iter().position(|other| other.name == name)
+                .ok_or(format!("'{}' is placed against '{}', which isn't on the label",
+                    element.name, name))?;
+            visiting.push(index);
+            let rect = place(target, elements, bounds, placed, visiting)?;
+            visiting.pop();
+            rect
+        },
+    };
+
+    let (width, height) = (element.width, element.height);
+    let left = target.x;
+    let centre = target.x + (target.width - width) / 2;
+    let right = target.right() - width;
+    let top = target.y;
+    let middle = target.y + (target.height - height) / 2;
+    let bottom = target.bottom() - height;
+    let (x, y) = match element.anchor.position {
+        Position::TopLeft => (left, top),
+        Position::Top => (centre, top),
+        Position::TopRight => (right, top),
+        Position::Left => (left, middle),
+        Position::Center => (centre, middle),
+        Position::Right => (right, middle),
+        Position::BottomLeft => (left, bottom),
+        Position::Bottom => (centre, bottom),
+        Position::BottomRight => (right, bottom),
+        Position::Above => (left, target.y - height),
+        Position::Below => (left, target.bottom()),
+        Position::LeftOf => (target.x - width, top),
+        Position::RightOf => (target.right(), top),
+    };
+
+    let rect = Rect { x: x + element.offset.0, y: y + element.offset.1, width, height };
+    placed[index] = Some(rect);
+    Ok(rect)
+}
+
+// Work out where every element goes within `bounds`, placing elements that others are anchored
+// to first
+pub fn resolve(elements: &[Element], bounds: Rect) -> Result<HashMap<String, Rect>, Box<dyn Error>> {
+    let mut placed = vec![None; elements.len()];
+    let mut rects = HashMap::new();
+    for (index, element) in elements.iter().enumerate() {
+        let rect = place(index, elements, bounds, &mut placed, &mut Vec::new())?;
+        rects.insert(element.name.to_string(), rect);
+    }
+    Ok(rects)
+}
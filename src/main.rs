@@ -16,189 +16,3262 @@
  * not, see <https://www.gnu.org/licenses/>.
  */
 
+mod adjust;
+mod audio;
+mod barcode;
+mod calibrate;
+#[cfg(feature = "ble")]
+mod ble;
+mod brother;
+mod color;
+mod crop;
+mod cue;
+mod debug;
+mod disc;
+mod discogs;
+mod dither;
+mod dymo;
+mod error;
+mod escpos;
+mod fetch;
+mod fill;
+mod fonts;
+mod guides;
+#[cfg(feature = "gui")]
+mod gui;
+mod init;
+mod layout;
+mod manifest;
+mod musicbrainz;
+mod output;
+mod palette;
+mod pdf;
+mod placement;
+mod preview;
+mod print;
+mod profile;
+mod schema;
+mod svg;
+mod template;
+mod text;
+mod theme;
+mod thermal;
+mod validate;
+mod watch;
+mod window;
+#[cfg(all(windows, feature = "windows-print"))]
+mod winprint;
+mod wizard;
+mod zpl;
+
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::io::{self, BufRead, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use ab_glyph::{FontRef, PxScale};
-use clap::Parser;
-use dirs::{self, download_dir};
-use imageproc::{drawing, image};
-use imageproc::image::{ImageBuffer, Pixel, Rgb, RgbImage};
+use ab_glyph::{Font, PxScale, ScaleFont};
+use clap::{Parser, Subcommand};
+use fast_image_resize::{FilterType, ResizeAlg, ResizeOptions, Resizer};
+use imageproc::image;
+use imageproc::image::metadata::Orientation;
+use imageproc::image::{
+    DynamicImage, ImageBuffer, ImageDecoder, ImageReader, Rgb, RgbImage, RgbaImage
+};
+use qrcode::QrCode;
 
+use schemars::JsonSchema;
 use serde_derive::Deserialize;
 
-// Printable Zink sheets are 2 x 3 inches (50 x 76mm)
+use adjust::Adjustments;
+use color::{Color, CoverColor, TextColor};
+use dither::Dither;
+use error::LabelError;
+use fill::{FillArea, Gradient};
+use fonts::LabelFont;
+use guides::CutGuides;
+use layout::{Anchor, Element, Placement, Position, Rect};
+use output::{OutputFormat, Overwrite};
+use profile::{Alignment, PrinterProfile};
+use theme::Theme;
+
+// Printable Zink sheets are 2 x 3 inches (50 x 76mm). This is the default sheet size
 const PRINTABLE_HEIGHT: u32 = 50;
-const PRITNABLE_WIDTH: u32 = 76;
+const PRINTABLE_WIDTH: u32 = 76;
 
-// MD labels (on Sony disks) are 53 x 36 mm safely
-const LABEL_HEIGHT: u32 = 50;
+// Other sheet sizes that can be given by name, in mm
+const SHEET_SIZES: [(&str, (f32, f32)); 4] = [
+    ("zink", (PRINTABLE_WIDTH as f32, PRINTABLE_HEIGHT as f32)),
+    ("a4", (210.0, 297.0)),
+    ("letter", (215.9, 279.4)),
+    ("4x6", (101.6, 152.4)),
+];
+
+// Face labels stand upright, 36mm wide and 50mm tall, which fits safely on Sony disks. These
+// are the defaults for labels that don't set their own size with `[label]`
 const LABEL_WIDTH: u32 = 36;
+const LABEL_HEIGHT: u32 = 50;
 
-// 600 dpi ~= 24 dpmm
-const DESIRED_DPMM: u32 = 24;
+// Spine strips run the height of a face label and are as wide as a MiniDisc case is deep
+const SPINE_WIDTH: u32 = 5;
 
-const LABEL_WIDTH_PX: u32 = LABEL_WIDTH * DESIRED_DPMM;
-const LABEL_HEIGHT_PX: u32 = LABEL_HEIGHT * DESIRED_DPMM;
-const PRITNABLE_WIDTH_PX: u32 = PRITNABLE_WIDTH * DESIRED_DPMM;
-const PRINTABLE_HEIGHT_PX: u32 = PRINTABLE_HEIGHT * DESIRED_DPMM;
+// The back of a case is bigger than a Zink sheet, so back inserts are as big a square as fits
+const BACK_WIDTH: u32 = 50;
+
+// 600 dpi ~= 24 dpmm. This is the default resolution, and the resolution that the pixel sizes
+// below are measured at. They're scaled to match when a different resolution is requested.
+const DESIRED_DPMM: u32 = 24;
+const MM_PER_INCH: f32 = 25.4;
 
+// Space between the edges of a label and what's on it, and between labels on a sheet. These are
+// the defaults, which can be given in mm by the theme, the layout or on the command line
 const PADDING: i32 = 40;
 const MARGIN: i32 = 20;
 
 const MD_LOGO_SIZE: u32 = 160;
+
+// QR codes are kept just big enough to scan reliably from a printed label, and are surrounded by
+// a quiet zone of light modules for scanners to find their edges
+const QR_CODE_SIZE: u32 = 240;
+const QR_QUIET_ZONE: u32 = 2;
+
+// Barcodes on back inserts are about two thirds of the nominal EAN-13 size, which still scans
+const BARCODE_WIDTH: u32 = 576;
+const BARCODE_HEIGHT: u32 = 264;
 const TEXT_SIZE_PT: f32 = 60.0;
 
+// Text too wide for the label is shrunk to fit, but no smaller than this
+const MIN_TEXT_SIZE_PT: f32 = 30.0;
+
+const SPINE_TEXT_SIZE_PT: f32 = 48.0;
+const TRACK_TEXT_SIZE_PT: f32 = 40.0;
+const FOOTER_TEXT_SIZE_PT: f32 = 32.0;
+
+// The parts of a face label that can be placed with `[layout]`
+const FACE_ELEMENTS: [&str; 7] = ["cover", "title", "artist", "year", "footer", "logo", "qr-code"];
+
+// Unless the theme says otherwise, labels have white text on a black background
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+const TEXT_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+// The sheet around the labels is left white, like the paper it's printed on
+const SHEET_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+// Blurred backgrounds are blurred at 1/8 of the label's size, by a radius in those pixels, and
+// dimmed to 60% of the cover's brightness
+const BLUR_SCALE: u32 = 8;
+const BLUR_SIGMA: f32 = 4.0;
+const BLUR_BRIGHTNESS: f32 = 0.6;
+
+// Distance between the tops of wrapped lines, relative to the text size
+const LINE_SPACING: f32 = 1.15;
+
+// Pixel sizes of everything on a sheet at a given output resolution
+struct Metrics {
+    dpmm: f32,
+    label_width_px: u32,
+    label_height_px: u32,
+    spine_width_px: u32,
+    back_width_px: u32,
+    printable_width_px: u32,
+    printable_height_px: u32,
+    /// The size of the sheet in mm, which is the page it's printed on unless another is given
+    printable_mm: (f32, f32),
+    padding: i32,
+    /// The gap between labels on a sheet
+    margin: i32,
+    /// Space around the edges of a sheet that labels aren't placed in, as top, right, bottom
+    /// and left
+    sheet_margin: [i32; 4],
+    md_logo_size: u32,
+    qr_code_size: u32,
+    barcode_width: u32,
+    barcode_height: u32,
+    text_size: f32,
+    min_text_size: f32,
+    spine_text_size: f32,
+    track_text_size: f32,
+    footer_text_size: f32,
+}
+
+impl Metrics {
+    fn new(dpmm: f32) -> Metrics {
+        let mm = |size: u32| (size as f32 * dpmm).round() as u32;
+        let scale = dpmm / DESIRED_DPMM as f32;
+
+        Metrics {
+            dpmm,
+            label_width_px: mm(LABEL_WIDTH),
+            label_height_px: mm(LABEL_HEIGHT),
+            spine_width_px: mm(SPINE_WIDTH),
+            back_width_px: mm(BACK_WIDTH),
+            printable_width_px: mm(PRINTABLE_WIDTH),
+            printable_height_px: mm(PRINTABLE_HEIGHT),
+            printable_mm: (PRINTABLE_WIDTH as f32, PRINTABLE_HEIGHT as f32),
+            padding: (PADDING as f32 * scale).round() as i32,
+            margin: (MARGIN as f32 * scale).round() as i32,
+            sheet_margin: [0, 0, 0, (2.0 * MARGIN as f32 * scale).round() as i32],
+            md_logo_size: (MD_LOGO_SIZE as f32 * scale).round() as u32,
+            qr_code_size: (QR_CODE_SIZE as f32 * scale).round() as u32,
+            barcode_width: (BARCODE_WIDTH as f32 * scale).round() as u32,
+            barcode_height: (BARCODE_HEIGHT as f32 * scale).round() as u32,
+            text_size: TEXT_SIZE_PT * scale,
+            min_text_size: MIN_TEXT_SIZE_PT * scale,
+            spine_text_size: SPINE_TEXT_SIZE_PT * scale,
+            track_text_size: TRACK_TEXT_SIZE_PT * scale,
+            footer_text_size: FOOTER_TEXT_SIZE_PT * scale,
+        }
+    }
+
+    fn from_dpi(dpi: u32) -> Metrics {
+        Metrics::new(dpi as f32 / MM_PER_INCH)
+    }
+
+    // Replace the default sizes with any that a theme sets in mm
+    fn with_theme(mut self, theme: &Theme) -> Metrics {
+        let size = |mm: Option<f32>, default: f32| mm.map_or(default, |mm| mm * self.dpmm);
+        self.text_size = size(theme.text_size, self.text_size);
+        self.min_text_size = size(theme.min_text_size, self.min_text_size);
+        self.spine_text_size = size(theme.spine_text_size, self.spine_text_size);
+        self.track_text_size = size(theme.track_text_size, self.track_text_size);
+        self.footer_text_size = size(theme.footer_text_size, self.footer_text_size);
+        self.padding = theme.padding.map_or(self.padding, |mm| self.px(mm));
+        self.margin = theme.margin.map_or(self.margin, |mm| self.px(mm));
+        self
+    }
+
+    // Make labels of another size, given in mm
+    fn with_label_size(mut self, geometry: &LabelGeometry) -> Metrics {
+        if let Some(width) = geometry.width_mm {
+            self.label_width_px = self.px(width).max(1) as u32;
+        }
+        if let Some(height) = geometry.height_mm {
+            self.label_height_px = self.px(height).max(1) as u32;
+        }
+        self
+    }
+
+    // Print on sheets of another size, given in mm
+    fn with_sheet_size(mut self, (width, height): (f32, f32)) -> Metrics {
+        self.printable_width_px = (width * self.dpmm).round() as u32;
+        self.printable_height_px = (height * self.dpmm).round() as u32;
+        self.printable_mm = (width, height);
+        self
+    }
+
+    // Convert a length in mm to pixels
+    fn px(&self, mm: f32) -> i32 {
+        (mm * self.dpmm).round() as i32
+    }
+}
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about=None)]
+#[command(version, about, long_about=None, after_help = error::EXIT_CODES)]
 struct Args {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Command,
+}
+
+// How labels are drawn, for every subcommand that draws them
+#[derive(clap::Args, Debug)]
+struct RenderArgs {
+    /// Output resolution in dots per inch, overriding the layout. Defaults to ~600 dpi
+    #[arg(long)]
+    dpi: Option<u32>,
+
+    /// Image to use in place of the bundled MiniDisc logo
+    #[arg(long)]
+    logo: Option<String>,
+
+    /// Don't draw the MiniDisc logo on any label
+    #[arg(long)]
+    no_logo: bool,
+
+    /// Name of an installed font to use for the label text, eg. "Noto Sans JP Bold"
+    #[arg(long)]
+    font: Option<String>,
+
+    /// Theme to style the labels with, by path or the name of a theme in the config directory.
+    /// The built in themes are classic, minimal, retro-90s and photo-full-bleed. Overrides any
+    /// theme given in the layout
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// Font to take characters from when the label font doesn't have them, by path or installed
+    /// name. Can be given more than once, fonts are tried in order after any in the layout
+    #[arg(long)]
+    fallback_font: Vec<String>,
+
+    /// Which labels to make for each disc. `single` makes the one given by its `label_type`,
+    /// `full` makes a face label, spine strip and case back together
+    #[arg(long, value_enum, default_value_t = LabelSet::Single)]
+    set: LabelSet,
+
+    /// Space in mm between the edges of each label and the text on it, overriding the layout
+    #[arg(long, value_name = "MM")]
+    padding: Option<f32>,
+
+    /// Space in mm between the labels on a sheet, overriding the layout
+    #[arg(long, value_name = "MM")]
+    margin: Option<f32>,
+
+    /// Space in mm to leave around the edges of each sheet, for sheets with a border that can't
+    /// be printed on. Either one size for every edge or `TOP,RIGHT,BOTTOM,LEFT`. Overrides the
+    /// layout. Defaults to twice the margin at the left edge only
+    #[arg(long, value_name = "MM")]
+    sheet_margin: Option<String>,
+
+    /// How to fit the labels onto sheets
+    #[arg(long, value_enum, default_value_t = Pack::Upright)]
+    pack: Pack,
+
+    /// Draw guides in the gaps between the labels on a sheet to cut them out along
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "lines")]
+    cut_guides: Option<CutGuides>,
+
+    /// Extend the artwork this many mm past the edges of each label, with crop marks at the
+    /// corners to cut along in place of any other cut guides. Overrides the layout's bleed
+    #[arg(long, value_name = "MM")]
+    bleed: Option<f32>,
+
+    /// Lay the labels out on sheets of die-cut labels, one to each, instead of on Zink sheets
+    #[arg(long, value_enum)]
+    sheet: Option<template::SheetTemplate>,
+
+    /// Brand of blank the labels are for, setting their size in place of the layout's
+    #[arg(long, value_enum)]
+    disc: Option<disc::Disc>,
+
+    /// Size of the sheets to lay the labels out on, as many to a sheet as fit. Either `zink`,
+    /// `a4`, `letter`, `4x6` or a size in mm (`WIDTHxHEIGHT`, eg. `100x150mm`). Defaults to zink
+    #[arg(long, conflicts_with = "sheet")]
+    sheet_size: Option<String>,
+
+    /// Stop with an error instead of warning about problems that would spoil a print, like covers
+    /// that are too small to print sharply or text that runs off a label or into its logo
+    #[arg(long)]
+    strict: bool,
+
+    /// Print in black and white only, for thermal label printers, dithering shades of gray with
+    /// the given method
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "floyd-steinberg")]
+    mono: Option<Dither>,
+
+    /// Color correction for the printer the sheets are for, by path or the name of a profile in
+    /// the config directory. The built in profile is zink. Overrides any profile in the layout
+    #[arg(long)]
+    printer_profile: Option<String>,
+
+    /// Shade the cover, text and logo areas of each label, and outline where it's trimmed and the
+    /// safe zone inside that, to check a theme keeps everything clear of the edges
+    #[arg(long)]
+    debug_safe_area: bool,
+
+    /// Outline every element and line of text on each label, and list where each one ended up in
+    /// label pixels, to track down anything overlapping
+    #[arg(long)]
+    debug_layout: bool,
+}
+
+// Where and how the sheets and labels are saved
+#[derive(clap::Args, Clone, Debug)]
+struct OutputArgs {
+    /// Where to save the sheets, or `-` to write them to stdout as PNG unless --format is given.
+    /// Can be left out when the labels are saved individually with --output-dir
+    #[arg(short, long, required_unless_present_any = ["output_dir", "dry_run"])]
+    output: Option<String>,
+
+    /// Directory to save every label in as an image of its own, named by --label-name
+    #[arg(long)]
+    output_dir: Option<String>,
+
+    /// Naming pattern for labels saved with --output-dir. `{artist}`, `{title}`, `{year}` and
+    /// `{type}` are taken from the label, `{num}` is its number starting at 1 and `{ext}` is the
+    /// extension of the format they're saved in. These can be used in the output path and sheet
+    /// pattern too, where they're taken from the first label on each sheet
+    #[arg(long, default_value = "label-{num}.{ext}")]
+    label_name: String,
+
+    /// Replace files that are already there. Without this nothing is written if any of the
+    /// output files exist
+    #[arg(long, conflicts_with = "no_clobber_rename")]
+    force: bool,
+
+    /// Save files alongside any that are already there, by adding a number to their names
+    #[arg(long)]
+    no_clobber_rename: bool,
+
+    /// Naming pattern used when the labels don't fit on a single sheet. `{stem}` and `{ext}` are
+    /// taken from the output path, `{num}` is the sheet number starting at 1
+    #[arg(long, default_value = "{stem}-{num}.{ext}")]
+    sheet_pattern: String,
+
+    /// Page size in mm (`WIDTHxHEIGHT`) used for PDF output. Defaults to the size of the sheet
+    #[arg(long)]
+    page_size: Option<String>,
+
+    /// ICC profile to embed in PNG and TIFF output, in place of sRGB. Overrides any profile in
+    /// the layout
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Write TIFF output in CMYK. It's converted through the ICC profile when a CMYK one is given,
+    /// and by a simple formula otherwise
+    #[arg(long)]
+    cmyk: bool,
+
+    /// Quality to save JPEG output at, from 1 to 100. Defaults to 90
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Format to save the sheets in, in place of the one given by the output's extension
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Width in mm that the printer prints across, for ESC/POS output. The default suits 80 mm
+    /// receipt paper, use 48 for 58 mm paper
+    #[arg(long, default_value_t = 72.0)]
+    escpos_width: f32,
+
+    /// How dark Zebra printers print ZPL output, from 0 to 30. Left as the printer has it unless
+    /// it's given
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=30))]
+    zpl_darkness: Option<u8>,
+
+    /// Also write where every label, element and line of text was placed on the sheets, in mm
+    /// and pixels, to this JSON file
+    #[arg(long, value_name = "PATH")]
+    emit_layout: Option<String>,
+
+    /// Also write a JSON manifest of every file saved, with the labels and sheets in each and a
+    /// hash of its contents, to this path
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<String>,
+
+    /// Draw and lay out the labels, checking everything they need, and report how many sheets
+    /// they'd take and what would be written, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+// The title, artist and cover are needed unless the details are imported from one of the sources
+// in the `import` group
+#[derive(clap::Args, Debug)]
+#[command(group = clap::ArgGroup::new("import").args(["from_audio", "cue", "playlist"]))]
+struct GenerateArgs {
+    /// Path to the cover art
+    #[arg(short, long, required_unless_present = "import")]
     cover: Option<String>,
 
-    #[arg(short, long)]
+    /// Title of the album
+    #[arg(short, long, required_unless_present = "import")]
     title: Option<String>,
 
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present = "import")]
     artist: Option<String>,
 
     #[arg(short, long)]
     release_year: Option<String>,
 
+    /// Audio file, or directory of them, to take the artist, album title, year and tracks from.
+    /// Anything given with the other options takes precedence over the tags
+    #[arg(long)]
+    from_audio: Option<String>,
+
+    /// CUE sheet to take the artist, title and tracks from. Anything given with the other
+    /// options takes precedence over the sheet
+    #[arg(long)]
+    cue: Option<String>,
+
+    /// M3U playlist to make a mix label from, with the tracks and running time taken from the
+    /// tags of the files it lists
+    #[arg(long)]
+    playlist: Option<String>,
+
+    /// How many of the label to put on the sheets
+    #[arg(long)]
+    copies: Option<u32>,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group = clap::ArgGroup::new("labels").args(["layout", "scan"]).required(true))]
+struct BatchArgs {
+    /// Layout file listing the labels to make, or `-` to read it from stdin
+    #[arg(short, long)]
+    layout: Option<String>,
+
+    /// Music library to make a label for every album in, grouping tracks by their tags. Covers
+    /// are taken from image files like `cover.jpg` next to the tracks
+    #[arg(long)]
+    scan: Option<String>,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Make the labels for a single disc, from the details given or the tags of its audio files
+    Generate(GenerateArgs),
+    /// Make labels for every disc in a layout file, or every album in a music library
+    Batch(BatchArgs),
+    /// Show the sheets for a layout in the terminal, to check them without saving anything
+    Preview(PreviewArgs),
+    /// Print the sheets for a layout, through CUPS or the Windows print spooler, or straight to a
+    /// printer over IPP
+    Print(PrintArgs),
+    /// Make the labels for a layout, and make them again whenever the layout or any of its covers
+    /// change
+    Watch(WatchArgs),
+    /// Open a window to edit a layout in, with a preview of each label that its text and logo
+    /// can be dragged around
+    #[cfg(feature = "gui")]
+    Gui(GuiArgs),
+    /// Look up a release on MusicBrainz or Discogs and add a label for it to a layout file
+    Fetch(FetchArgs),
+    /// Print a JSON Schema for layout files, for editors to check and complete them with
+    Schema,
+    /// Check a layout for mistakes, like missing covers or text that won't fit, without saving
+    /// anything
+    Validate(ValidateArgs),
+    /// Add labels to a layout by answering questions about them, with a warning for anything that
+    /// won't fit
+    New(NewArgs),
+    /// Write a starter layout, with comments on its settings and placeholder labels to fill in
+    Init(InitArgs),
+    /// Save a test sheet with rulers, a grid and registration marks, to print and measure how far
+    /// a printer scales and shifts what it prints
+    Calibrate(CalibrateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct FetchArgs {
+    #[arg(short, long)]
+    artist: Option<String>,
+
+    /// Title of the album to search for
+    #[arg(short = 'b', long)]
+    album: Option<String>,
+
+    /// MusicBrainz release ID to look up instead of searching by artist and album
+    #[arg(long)]
+    mbid: Option<String>,
+
+    /// Discogs release URL or ID to look up. Covers are only downloaded from Discogs when an
+    /// access token is set in DISCOGS_TOKEN
+    #[arg(long)]
+    discogs: Option<String>,
+
+    /// Layout file to add the label to. It's created if it doesn't exist
+    #[arg(short, long)]
+    layout: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct CalibrateArgs {
+    /// Where to save the test sheet. Save it as a PDF to print it at its actual size
     #[arg(short, long)]
     output: String,
 
+    /// Size of the sheet, the same as for --sheet-size when making labels. Defaults to zink
+    #[arg(long)]
+    sheet_size: Option<String>,
+
+    /// Resolution in dots per inch. Defaults to ~600 dpi
+    #[arg(long)]
+    dpi: Option<u32>,
+
+    /// Format to save the sheet in, in place of the one given by the output's extension
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Replace the output if it already exists
+    #[arg(long, conflicts_with = "no_clobber_rename")]
+    force: bool,
+
+    /// Save the sheet alongside any file that's already there, by adding a number to its name
+    #[arg(long)]
+    no_clobber_rename: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct InitArgs {
+    /// How many placeholder labels to start with
+    #[arg(short = 'n', long, default_value_t = 1)]
+    labels: usize,
+
+    /// Where to write the layout, or `-` to write it to stdout
+    #[arg(short, long, default_value = "layout.toml")]
+    layout: String,
+
+    /// Replace the layout if it already exists
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct PreviewArgs {
+    /// Layout file listing the labels to show, or `-` to read it from stdin
+    #[arg(short, long)]
+    layout: String,
+
+    /// How to draw the sheets. Picked from what the terminal supports unless it's given
+    #[arg(long, value_enum, default_value_t = preview::Protocol::Auto)]
+    protocol: preview::Protocol,
+
+    /// Width in columns to show each sheet at. Defaults to as big as fits in the terminal
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Show the sheets in a window instead, and draw them again whenever the layout or any of
+    /// its covers change
+    #[arg(long, conflicts_with_all = ["protocol", "width"])]
+    watch_preview: bool,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PrintArgs {
+    /// Layout file listing the labels to print, or `-` to read it from stdin
     #[arg(short, long)]
+    #[cfg_attr(feature = "ble", arg(required_unless_present = "list_devices"))]
+    #[cfg_attr(not(feature = "ble"), arg(required = true))]
     layout: Option<String>,
+
+    /// Printer to print on, either the name of a CUPS printer, or of a Windows printer when built
+    /// with `windows-print`, or the `ipp://` or `ipps://` address of a printer to send the sheets
+    /// to directly. Defaults to the default printer
+    #[arg(short, long)]
+    printer: Option<String>,
+
+    /// Paper to print on, as a media name the printer knows, like `A6` for CUPS or
+    /// `iso_a6_105x148mm` over IPP. Defaults to paper the size of the page
+    #[arg(long)]
+    media: Option<String>,
+
+    /// How the printer should fit the sheets to the paper. Printing at actual size keeps the
+    /// labels the size they're meant to be
+    #[arg(long, value_enum, default_value_t = print::Scaling::None)]
+    scaling: print::Scaling,
+
+    /// Page size in mm (`WIDTHxHEIGHT`) that each sheet is centred on. Defaults to the size of
+    /// the sheet
+    #[arg(long)]
+    page_size: Option<String>,
+
+    /// Print each label on its own on a Bluetooth label printer instead, like a Niimbot D11 or
+    /// Phomemo M02, given by the name or address it's listed with. Defaults to the first one found
+    #[cfg(feature = "ble")]
+    #[arg(long, num_args = 0..=1, default_missing_value = "",
+        conflicts_with_all = ["printer", "media", "scaling", "page_size"])]
+    device: Option<String>,
+
+    /// List the Bluetooth label printers that are nearby
+    #[cfg(feature = "ble")]
+    #[arg(long, exclusive = true)]
+    list_devices: bool,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    /// Layout file listing the labels to make
+    layout: String,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[cfg(feature = "gui")]
+#[derive(clap::Args, Debug)]
+struct GuiArgs {
+    /// Layout file to edit. It's created when it's first saved if it doesn't exist yet
+    #[arg(short, long, default_value = "layout.toml")]
+    layout: String,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct NewArgs {
+    /// Layout file to add the labels to. It's created if it doesn't exist
+    #[arg(short, long, default_value = "layout.toml")]
+    layout: String,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// Layout file to check, or `-` to read it from stdin
+    #[arg(short, long)]
+    layout: String,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum LabelSet {
+    Single,
+    Full,
+}
+
+// How labels are fitted onto sheets
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+enum Pack {
+    /// Keep every label upright
+    #[default]
+    Upright,
+    /// Turn the labels sideways when that fits them on fewer sheets
+    Rotate,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Default)]
 struct Config {
+    dpi: Option<u32>,
+    theme: Option<String>,
+    /// Where each element of a face label goes, in place of the theme's layout
+    #[serde(default)]
+    layout: HashMap<String, Placement>,
+    /// Smallest height in mm that text will be shrunk to when it's too wide for the label
+    min_text_size: Option<f32>,
+    text_overflow: Option<TextOverflow>,
+    /// Space in mm between the edges of each label and the text on it, in place of the theme's
+    padding: Option<f32>,
+    /// Space in mm between the labels on a sheet, in place of the theme's
+    margin: Option<f32>,
+    sheet_margin: Option<SheetMargin>,
+    /// Fonts, by path or installed name, to take characters from when the label font lacks them
+    #[serde(default)]
+    fallback_fonts: Vec<String>,
+    logo: Option<LogoConfig>,
+    accent: Option<AccentConfig>,
+    /// Size of the labels and where the cover and text go on them, in place of the theme's
+    label: Option<LabelGeometry>,
+    /// Color of the sheet around and between the labels
+    sheet_color: Option<Color>,
+    /// How far in mm to extend the artwork past the edges of each label, with crop marks at the
+    /// corners to cut along
+    bleed: Option<f32>,
+    /// Smallest width or height in pixels a cover can be without being warned about. Defaults to
+    /// the width of a label at the output resolution
+    min_cover_size: Option<u32>,
+    resize_filter: Option<ResizeFilter>,
+    sharpen: Option<SharpenConfig>,
+    /// Color correction for the printer, by path or name, applied to each sheet before it's saved
+    printer_profile: Option<String>,
+    /// Path to an ICC profile to embed in saved sheets in place of sRGB
+    icc_profile: Option<String>,
+    /// Where the printer puts things on the sheet, in place of the printer profile's alignment
+    printer: Option<Alignment>,
     labels: Vec<Label>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Default)]
 struct Label {
+    label_type: Option<LabelType>,
+    /// How many of the label to put on the sheets, for making several dubs of the same disc.
+    /// With `--set full` each copy is a full set. Defaults to 1
+    copies: Option<u32>,
     title: String,
     artist: String,
     release_year: Option<String>,
+    /// Path to the cover art. Only face labels need one
+    #[serde(default)]
     cover: String,
+    cover_fit: Option<CoverFit>,
+    cover_crop: Option<CoverCrop>,
+    /// Brightness, contrast and saturation changes to make to the cover. Can be given for every
+    /// label under `[defaults]`
+    adjust: Option<Adjustments>,
+    /// Cover image read from somewhere other than a file, like the tags of an audio file. Used
+    /// in place of `cover` when it's set
+    #[serde(skip)]
+    cover_data: Option<Vec<u8>>,
+    /// Tracks, listed in order on the back insert, and below the text of the face label if
+    /// `tracks_on_face` is set
+    #[serde(default)]
+    tracks: Vec<Track>,
+    tracks_on_face: Option<bool>,
+    /// Total running time, shown on the back insert
+    runtime: Option<String>,
+    /// Link to the album online, eg. on Bandcamp or Spotify, drawn as a QR code in the bottom
+    /// left corner of the cover on the face label
+    url: Option<String>,
+    /// Shown in a small line along the bottom of the face label
+    record_label: Option<String>,
+    catalog_number: Option<String>,
+    /// Picks the color of the accent strip from the layout's `[accent.genres]`
+    genre: Option<String>,
+    /// Color of the accent strip, in place of the one for the genre
+    accent_color: Option<CoverColor>,
+    /// EAN-13 or UPC-A code to print as a barcode in the bottom right corner of the back insert
+    barcode: Option<String>,
+    logo: Option<LogoConfig>,
+    /// Paths to font files for each line of text, replacing the default font
+    title_font: Option<String>,
+    artist_font: Option<String>,
+    year_font: Option<String>,
+    /// Horizontal alignment of each line of text, left aligned by default
+    title_align: Option<Align>,
+    artist_align: Option<Align>,
+    year_align: Option<Align>,
+    text_orientation: Option<TextOrientation>,
+    #[serde(flatten)]
+    style: Style,
+    /// Extra text placed anywhere on the label, given as `[[labels.text]]` tables
+    #[serde(default)]
+    text: Vec<FreeText>,
 }
 
-fn cover_image(path: &str) -> Result<RgbImage, Box<dyn Error>> {
-    let mut cover_image = image::open(fs::canonicalize(path)?)
-        .unwrap()
-        .into_rgb8();
+// Styles that can be given for every label under `[defaults]` and for each label in `[[labels]]`.
+// A label's own setting takes precedence, then `[defaults]`, then the theme, and then the built in
+// style. The defaults are merged into each label when the layout is read, see `parse_config`.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct Style {
+    /// Height of the title, artist and year in mm
+    text_size: Option<f32>,
+    /// Color of the label around the cover and behind the text, which can be taken from the
+    /// cover. Can also be given as `background_color`
+    #[serde(alias = "background_color")]
+    background: Option<CoverColor>,
+    /// A color, or `auto` to pick black or white for each line depending on what's behind it
+    text_color: Option<TextColor>,
+    /// Set the title, artist and year in capitals
+    uppercase: Option<bool>,
+    /// A band of translucent color behind the title, artist and year
+    scrim: Option<ScrimConfig>,
+    /// A gradient in place of the flat background color
+    gradient: Option<Gradient>,
+}
 
-    cover_image = image::imageops::resize(
-        &cover_image, LABEL_WIDTH_PX as u32, LABEL_WIDTH_PX as u32,
-        image::imageops::FilterType::Triangle
-    );
+// A piece of text placed at a fixed position on a label, eg. a note like "LP2 \u{2022} 160 min"
+// or a dedication. It goes on the label given by the entry's `label_type` unless it says
+// otherwise, which is useful when a full set is being made.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+struct FreeText {
+    content: String,
+    /// Top left of the text in mm from the top left of the label
+    x: f32,
+    y: f32,
+    /// Height of the text in mm, the same as track listings by default
+    size: Option<f32>,
+    color: Option<Color>,
+    label_type: Option<LabelType>,
+}
+
+// A track is either just its title, or a table with its title and duration, eg.
+// `{ title = "Idioteque", duration = "5:09" }`
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(untagged)]
+enum Track {
+    Title(String),
+    Detailed { title: String, duration: Option<String> },
+}
+
+impl Track {
+    fn title(&self) -> &str {
+        match self {
+            Track::Title(title) | Track::Detailed { title, .. } => title,
+        }
+    }
+
+    fn duration(&self) -> Option<&str> {
+        match self {
+            Track::Title(_) => None,
+            Track::Detailed { duration, .. } => duration.as_deref(),
+        }
+    }
+}
+
+// Face labels go on the disc itself, with the cover art above the text. Spine labels are a narrow
+// strip for the edge of the case with the artist and title along it, and backs are an insert for
+// the back of the case with the track list.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum LabelType {
+    #[default]
+    Face,
+    Spine,
+    Back,
+}
+
+// Where the cover goes on a face label. It's normally a square at the top with the text below it,
+// but can be stretched to fill the whole label with the text over the bottom of it. `blurred` keeps
+// the square at the top, and fills the rest of the label with a blurred and darkened copy of it.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum CoverLayout {
+    #[default]
+    Top,
+    FullBleed,
+    Blurred,
+}
+
+// How cover art that isn't the same shape as the space for it is made to fit. `fill` crops it
+// around the middle, `fit` scales it down to fit inside with the background showing either side,
+// `stretch` squashes it, and `smart` crops it around its most detailed part. Covers at the top of
+// the label are stretched unless told otherwise, and full bleed covers are filled.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum CoverFit {
+    Fill,
+    Fit,
+    Stretch,
+    Smart,
+}
+
+// The part of the cover art to use, in pixels of the original image, eg. to pick one panel of a
+// gatefold: `cover_crop = { x = 1400, y = 0, w = 1400, h = 1400 }`
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+struct CoverCrop {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+// Which way text runs on a label. Rotated text is turned a quarter turn clockwise to read from
+// top to bottom, and vertical text is set in columns with CJK characters kept upright (tategaki).
+// Rotated and vertical lines are stacked from right to left.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum TextOrientation {
+    #[default]
+    Horizontal,
+    Rotated,
+    Vertical,
+}
+
+// The filter covers are scaled with. Lanczos is the sharpest, and the others trade sharpness for
+// fewer halos around hard edges, down to nearest which keeps pixel art blocky.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn resize_alg(self) -> ResizeAlg {
+        match self {
+            ResizeFilter::Nearest => ResizeAlg::Nearest,
+            ResizeFilter::Triangle => ResizeAlg::Convolution(FilterType::Bilinear),
+            ResizeFilter::CatmullRom => ResizeAlg::Convolution(FilterType::CatmullRom),
+            ResizeFilter::Gaussian => ResizeAlg::Convolution(FilterType::Gaussian),
+            ResizeFilter::Lanczos3 => ResizeAlg::Convolution(FilterType::Lanczos3),
+        }
+    }
+}
+
+// An unsharp mask run over covers once they've been scaled down, which brings back the crispness
+// lost in scaling, eg. `sharpen = { sigma = 1.0, threshold = 2 }`. `sigma` is how far in pixels
+// the blur of the mask spreads, and edges with less contrast than `threshold` are left alone.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct SharpenConfig {
+    sigma: Option<f32>,
+    threshold: Option<i32>,
+}
+
+// What to do with text that's too wide for the label. Wrapped text is also shrunk if the wrapped
+// lines don't fit, shrunk text is drawn at its minimum size even if it's still too wide, and
+// ellipsis cuts the text short at full size.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum TextOverflow {
+    #[default]
+    Wrap,
+    Shrink,
+    Ellipsis,
+}
+
+// Logo settings, given for the whole layout and/or for each label. Anything not set on a label
+// falls back to the layout, then to the defaults.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct LogoConfig {
+    enabled: Option<bool>,
+    position: Option<LogoPosition>,
+    /// Width and height of the logo in mm
+    size: Option<f32>,
+    opacity: Option<f32>,
+}
+
+// A band of translucent color behind the title, artist and year, so they can be read over busy
+// cover art, eg. `scrim = { color = "black", opacity = 0.6 }`
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct ScrimConfig {
+    enabled: Option<bool>,
+    color: Option<Color>,
+    opacity: Option<f32>,
+}
+
+// The size of the labels, for blanks other than Sony's or stickers that have been cut down, and
+// the parts of a face label that the cover and text go in, eg.
+//
+//   [label]
+//   width_mm = 34
+//   height_mm = 48
+//   cover_area = { x = 2, y = 2, width = 30, height = 30 }
+//
+// Spines and backs are as tall as the face. Full bleed covers fill the whole label whatever the
+// cover area is.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct LabelGeometry {
+    width_mm: Option<f32>,
+    height_mm: Option<f32>,
+    /// Where the cover goes, defaulting to a square across the top of the label
+    cover_area: Option<Area>,
+    /// Where the title, artist and year go, defaulting to below the cover inside the padding
+    text_area: Option<Area>,
+}
+
+// A part of a label, in mm from its top left corner
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+struct Area {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+// Space around the edges of a sheet that labels aren't placed in, in mm, eg. for Zink sheets,
+// which can't be printed right to their edges
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum SheetMargin {
+    /// The same space at every edge
+    All(f32),
+    /// Space at the top, right, bottom and left edges
+    Edges([f32; 4]),
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum LogoPosition {
+    Corner(Corner),
+    /// Top left of the logo in mm from the top left of the label
+    At { x: f32, y: f32 },
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// Accent strips are a bar of color along one edge of each label, so that discs can be told apart
+// by genre at a glance, eg.
+//
+//   [accent]
+//   genres = { rock = "#c0392b", jazz = "#2e86c1" }
+//
+// Labels whose genre isn't listed get `color`, or no strip if that isn't set.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+struct AccentConfig {
+    edge: Option<Edge>,
+    /// Thickness of the strip in mm
+    width: Option<f32>,
+    color: Option<CoverColor>,
+    #[serde(default)]
+    genres: HashMap<String, Color>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Edge {
+    Top,
+    Bottom,
+    #[default]
+    Left,
+    Right,
+}
+
+impl LogoConfig {
+    // Fill in anything unset from the more general settings
+    fn or(&self, fallback: &LogoConfig) -> LogoConfig {
+        LogoConfig {
+            enabled: self.enabled.or(fallback.enabled),
+            position: self.position.or(fallback.position),
+            size: self.size.or(fallback.size),
+            opacity: self.opacity.or(fallback.opacity),
+        }
+    }
+}
+
+impl SheetMargin {
+    // The space at the top, right, bottom and left edges
+    fn edges(self) -> [f32; 4] {
+        match self {
+            SheetMargin::All(mm) => [mm; 4],
+            SheetMargin::Edges(edges) => edges,
+        }
+    }
+}
+
+impl LabelGeometry {
+    // Fill in anything unset from the more general settings
+    fn or(&self, fallback: &LabelGeometry) -> LabelGeometry {
+        LabelGeometry {
+            width_mm: self.width_mm.or(fallback.width_mm),
+            height_mm: self.height_mm.or(fallback.height_mm),
+            cover_area: self.cover_area.or(fallback.cover_area),
+            text_area: self.text_area.or(fallback.text_area),
+        }
+    }
+}
+
+impl Area {
+    // The area in pixels, which has to be inside a label of the given size in mm
+    fn px(
+        &self,
+        name: &str,
+        (width, height): (f32, f32),
+        metrics: &Metrics
+    ) -> Result<Rect, Box<dyn Error>> {
+        if self.x < 0.0 || self.y < 0.0 || self.width <= 0.0 || self.height <= 0.0
+            || self.x + self.width > width || self.y + self.height > height
+        {
+            return Err(format!("the {} goes outside of the {}x{}mm label", name, width, height)
+                .into());
+        }
+        // Rounding to pixels mustn't push the area off the edge of the label either
+        let (x, y) = (metrics.px(self.x), metrics.px(self.y));
+        Ok(Rect {
+            x,
+            y,
+            width: metrics.px(self.width).min(metrics.label_width_px as i32 - x).max(1),
+            height: metrics.px(self.height).min(metrics.label_height_px as i32 - y).max(1),
+        })
+    }
+}
+
+// Decode an image the right way up. Phone cameras save photos sideways and record which way they
+// were held in the EXIF orientation tag, which the pixels have to be turned to match.
+fn decode_upright<R: BufRead + Seek>(
+    reader: ImageReader<R>
+) -> Result<DynamicImage, image::ImageError> {
+    let mut decoder = reader.with_guessed_format().map_err(image::ImageError::IoError)?
+        .into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+// Load the cover art, cropped if the label only wants part of it, and adjusted for printing
+fn load_cover(label_info: &Label) -> Result<DynamicImage, Box<dyn Error>> {
+    let cover = match &label_info.cover_data {
+        Some(data) => decode_upright(ImageReader::new(Cursor::new(data))).map_err(|source| {
+            let what = format!("the embedded cover for '{}'", label_info.title);
+            LabelError::Decode { what, source }
+        })?,
+        None => {
+            let what = format!("cover '{}'", label_info.cover);
+            let reader = fs::canonicalize(&label_info.cover)
+                .and_then(ImageReader::open)
+                .map_err(|source| LabelError::MissingFile { what: what.clone(), source })?;
+            decode_upright(reader).map_err(|source| LabelError::Decode { what, source })?
+        },
+    };
 
-    Ok(cover_image)
+    let cover = match label_info.cover_crop {
+        Some(CoverCrop { x, y, w, h }) => {
+            let (right, bottom) = (x.saturating_add(w), y.saturating_add(h));
+            if w == 0 || h == 0 || right > cover.width() || bottom > cover.height() {
+                return Err(format!(
+                    "the crop of the cover for '{}' goes outside of the {}x{} image",
+                    label_info.title, cover.width(), cover.height()
+                ).into());
+            }
+            cover.crop_imm(x, y, w, h)
+        },
+        None => cover,
+    };
+    Ok(label_info.adjust.unwrap_or_default().apply(cover))
 }
 
-fn overlay_text(
-    label: RgbImage,
-    title_text: &String,
-    artist_text: &String,
-    release_year: &Option<String>
+// Scale the cover art to the space for it on the label, which is a square at the top, or the whole
+// label for full bleed covers. Covers that are fitted inside it have `background` either side.
+fn cover_image(
+    cover: &DynamicImage,
+    fit: Option<CoverFit>,
+    background: Rgb<u8>,
+    context: &RenderContext
 ) -> Result<RgbImage, Box<dyn Error>> {
-    const TEXT_AREA_HEIGHT: u32 = LABEL_HEIGHT_PX - LABEL_WIDTH_PX;
-    const LINE_HEIGHT: u32 = TEXT_AREA_HEIGHT / 3;
+    let (metrics, layout) = (&context.metrics, context.cover_layout);
+    let options = ResizeOptions::new().resize_alg(context.resize_filter.resize_alg());
+    let (width, height) = match layout {
+        CoverLayout::Top | CoverLayout::Blurred =>
+            (context.cover_area.width as u32, context.cover_area.height as u32),
+        CoverLayout::FullBleed => (metrics.label_width_px, metrics.label_height_px),
+    };
+    let fit = fit.unwrap_or(match layout {
+        CoverLayout::FullBleed => CoverFit::Fill,
+        _ => CoverFit::Stretch,
+    });
+
+    let scaled = match fit {
+        CoverFit::Stretch => resize_image(cover, width, height, &options)?,
+        CoverFit::Fill => resize_image(
+            cover, width, height, &options.fit_into_destination(Some((0.5, 0.5)))
+        )?,
+        CoverFit::Fit => {
+            let scale = (width as f32 / cover.width() as f32)
+                .min(height as f32 / cover.height() as f32);
+            let scaled = resize_image(cover,
+                ((cover.width() as f32 * scale).round() as u32).clamp(1, width),
+                ((cover.height() as f32 * scale).round() as u32).clamp(1, height),
+                &options
+            )?;
+            let mut image = RgbImage::from_pixel(width, height, background);
+            image::imageops::overlay(&mut image, &scaled,
+                (width - scaled.width()) as i64 / 2, (height - scaled.height()) as i64 / 2
+            );
+            image
+        },
+        CoverFit::Smart => {
+            let (x, y, crop_width, crop_height) = crop::smart_crop(cover, width, height);
+            let crop = options.crop(x as f64, y as f64, crop_width as f64, crop_height as f64);
+            resize_image(cover, width, height, &crop)?
+        },
+    };
+
+    Ok(match &context.sharpen {
+        Some(sharpen) => image::imageops::unsharpen(
+            &scaled, sharpen.sigma.unwrap_or(1.0), sharpen.threshold.unwrap_or(2)
+        ),
+        None => scaled,
+    })
+}
 
-    let font_scale = PxScale::from(TEXT_SIZE_PT);
+// Scale an image to `width` by `height`. This uses SIMD where the CPU has it, which is many times
+// quicker than `imageops` for the multi-megapixel scans that covers often are.
+fn resize_image(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    options: &ResizeOptions
+) -> Result<RgbImage, Box<dyn Error>> {
+    let source = DynamicImage::ImageRgb8(image.to_rgb8());
+    let mut resized = DynamicImage::new_rgb8(width, height);
+    Resizer::new().resize(&source, &mut resized, options)?;
+    Ok(resized.into_rgb8())
+}
 
-    let font = FontRef::try_from_slice(
-        include_bytes!("../res/liberation_sans/LiberationSans-Bold.ttf")
+// The background of a label with a blurred cover layout. The cover is cropped to fill the label,
+// then blurred and darkened so that it doesn't compete with the text. It's blurred at a fraction
+// of its size, which is much quicker and looks the same once it's been scaled back up.
+fn blurred_background(
+    cover: &DynamicImage,
+    metrics: &Metrics
+) -> Result<RgbImage, Box<dyn Error>> {
+    let options = ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Bilinear));
+    let (width, height) = (metrics.label_width_px, metrics.label_height_px);
+    let small = resize_image(cover, (width / BLUR_SCALE).max(1), (height / BLUR_SCALE).max(1),
+        &options.fit_into_destination(Some((0.5, 0.5)))
     )?;
+    let blurred = image::imageops::blur(&small, BLUR_SIGMA);
+    let mut background = resize_image(&DynamicImage::ImageRgb8(blurred), width, height, &options)?;
+    for pixel in background.pixels_mut() {
+        pixel.0 = pixel.0.map(|c| (c as f32 * BLUR_BRIGHTNESS).round() as u8);
+    }
+    Ok(background)
+}
 
-    let first_line_y = LABEL_WIDTH_PX as i32 + PADDING;
-    let second_line_y = first_line_y + font_scale.y as i32 + PADDING;
-    let third_line_y = second_line_y + LINE_HEIGHT as i32 as i32;
+// A line of text placed on a label. `x` and `y` are the top left of the line in label pixels
+#[derive(Clone)]
+struct TextLine {
+    text: String,
+    font: LabelFont,
+    x: i32,
+    y: i32,
+    scale: PxScale,
+    orientation: TextOrientation,
+    color: Rgb<u8>,
+}
 
-    let white = Rgb([255,255,255]);
+// The part of a label that its text is laid out in, in label pixels
+struct TextArea {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
 
-    let mut final_label = drawing::draw_text(&label, white, PADDING, first_line_y,
-        font_scale , &font, &title_text
-    );
-    final_label = drawing::draw_text(&final_label, white, PADDING, second_line_y,
-        font_scale , &font, &artist_text
-    );
-    final_label = match release_year {
-        Some(year) => drawing::draw_text(
-            &final_label, white, PADDING, third_line_y,
-            font_scale , &font, &year
-        ),
-        None => final_label
-    };
-    Ok(final_label)
+// Everything shared between all of the labels being rendered
+struct RenderContext {
+    metrics: Metrics,
+    md_logo: Option<RgbaImage>,
+    logo: LogoConfig,
+    accent: AccentConfig,
+    font: LabelFont,
+    /// Fonts for each line of text, used when a label doesn't set its own
+    fonts: LabelFonts,
+    cover_layout: CoverLayout,
+    /// Where the cover goes on a face label, in label pixels
+    cover_area: Rect,
+    /// Where the text goes on a face label, unless it's left below the cover
+    text_area: Option<Rect>,
+    layout: HashMap<String, Placement>,
+    background: CoverColor,
+    text_color: TextColor,
+    scrim: Option<ScrimConfig>,
+    gradient: Option<Gradient>,
+    align: Align,
+    text_overflow: TextOverflow,
+    min_cover_size: u32,
+    resize_filter: ResizeFilter,
+    sharpen: Option<SharpenConfig>,
+    /// Problems that would only be warned about are errors
+    strict: bool,
 }
 
-fn overlay_minidisc_logo(image: &mut RgbImage) -> Result<(), Box<dyn Error>> {
-    let md_logo_path = download_dir()
-        .ok_or("can't get download dir")?
-        .as_path().join("md30wiki_color.png");
+// A piece of text to lay out on a label, and how to lay it out
+struct TextField<'a> {
+    text: &'a str,
+    font: &'a LabelFont,
+    align: Align,
+    wraps: bool,
+    color: Rgb<u8>,
+}
 
-    let md_logo = image::imageops::resize(
-        &image::open(md_logo_path)?.into_rgb8(),
-        MD_LOGO_SIZE as u32, MD_LOGO_SIZE as u32,
-        image::imageops::FilterType::CatmullRom
-    );
-    image::imageops::overlay(image, &md_logo,
-        (LABEL_WIDTH_PX - (PADDING / 2) as u32 - MD_LOGO_SIZE) as i64,
-        (LABEL_HEIGHT_PX - (PADDING / 2) as u32 - MD_LOGO_SIZE) as i64,
-    );
+// The fonts used for each line of text on a label
+struct LabelFonts {
+    title: LabelFont,
+    artist: LabelFont,
+    year: LabelFont,
+}
 
-    Ok(())
+// How a single label is styled, once its own settings have been merged with the defaults and
+// the theme
+struct LabelStyle {
+    fonts: LabelFonts,
+    background: Rgb<u8>,
+    text_color: Rgb<u8>,
+    /// Pick the color of each line of text once the label has been drawn, see `contrasting_color`
+    auto_text_color: bool,
+    text_size: f32,
+    uppercase: bool,
+    scrim: Option<ScrimConfig>,
+    accent: Option<Rgb<u8>>,
+    gradient: Option<Gradient>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Parse the main CLI options
-    let args = Args::parse();
+impl LabelStyle {
+    // A blank label in the background color or gradient. Gradients that are just for the text
+    // start at `text_top`, below whatever covers the top of the label.
+    fn background_image(&self, width: u32, height: u32, text_top: i32) -> RgbImage {
+        let mut image = RgbImage::from_pixel(width, height, self.background);
+        if let Some(gradient) = &self.gradient {
+            let top = if gradient.area == FillArea::Text { text_top } else { 0 };
+            let area = Rect { x: 0, y: top, width: width as i32, height: height as i32 - top };
+            fill::fill_gradient(&mut image, area, gradient);
+        }
+        image
+    }
+
+    fn case(&self, text: &str) -> String {
+        if self.uppercase { text.to_uppercase() } else { text.to_string() }
+    }
+}
 
-    let label_config: Config;
+// A label ready to be placed on a sheet. The artwork is kept separate from the text so that
+// vector backends can emit the text as text rather than pixels
+#[derive(Clone)]
+struct RenderedLabel {
+    art: RgbImage,
+    text: Vec<TextLine>,
+    /// Where each element of a face label ended up, by the names used in `[layout]`, along with
+    /// the area that the label's text is laid out in as `text-area`
+    elements: HashMap<String, Rect>,
+    /// Pixels of artwork around each edge of the label past where it's cut
+    bleed: i32,
+}
 
-    if args.layout.is_some() {
-        let toml_string = fs::read_to_string(args.layout.ok_or("")?)?;
-        println!("{:?}", toml_string);
-        label_config = toml::from_str(&toml_string)?;
-    } else {
-        let artist_text = args.artist.ok_or("")?.to_uppercase();
-        let title_text = args.title.ok_or("")?.to_uppercase();
-        let release_year = args.release_year.and_then(|f| Some(f.to_uppercase()));
-        let cover_path = args.cover.ok_or("")?;
-
-        label_config = Config {
-            labels: vec![
-                Label{ title: title_text, artist: artist_text, release_year: release_year, cover: cover_path }
-            ]
+impl RenderedLabel {
+    // Turn the label a quarter turn clockwise. Its text is drawn into the art first, because
+    // lines can't be set in every orientation that they'd be turned to
+    fn turned(self) -> RenderedLabel {
+        let height = self.art.height() as i32;
+        RenderedLabel {
+            art: image::imageops::rotate90(&overlay_text(&self.art, &self.text)),
+            text: Vec::new(),
+            elements: self.elements.into_iter()
+                .map(|(name, rect)| (name, Rect {
+                    x: height - rect.bottom(), y: rect.x, width: rect.height, height: rect.width
+                }))
+                .collect(),
+            bleed: self.bleed,
+        }
+    }
+
+    // Extend the artwork `bleed` pixels past each edge of the label, so that cutting it out a
+    // little outside the line doesn't leave a sliver of the sheet showing. The edges of the art
+    // are mirrored out into the bleed, which carries covers and backgrounds on seamlessly.
+    fn with_bleed(self, bleed: i32) -> RenderedLabel {
+        if bleed <= 0 {
+            return self;
+        }
+        let (width, height) = (self.art.width() as i32, self.art.height() as i32);
+        let mirror = |i: i32, size: i32| match i {
+            i if i < 0 => (-i - 1).min(size - 1),
+            i if i >= size => (2 * size - i - 1).max(0),
+            i => i,
         };
+        let art = RgbImage::from_fn((width + 2 * bleed) as u32, (height + 2 * bleed) as u32,
+            |x, y| *self.art.get_pixel(
+                mirror(x as i32 - bleed, width) as u32, mirror(y as i32 - bleed, height) as u32
+            )
+        );
+        RenderedLabel {
+            art,
+            text: self.text.into_iter()
+                .map(|line| TextLine { x: line.x + bleed, y: line.y + bleed, ..line })
+                .collect(),
+            elements: self.elements.into_iter()
+                .map(|(name, rect)| (name, Rect { x: rect.x + bleed, y: rect.y + bleed, ..rect }))
+                .collect(),
+            bleed: self.bleed + bleed,
+        }
     }
+}
 
-    // Create a white background image the same size as a Zink printable sticker
-    let mut printable_area: RgbImage = ImageBuffer::new(PRITNABLE_WIDTH_PX, PRINTABLE_HEIGHT_PX);
-    for (_x, _y, p) in printable_area.enumerate_pixels_mut() {
-        p.invert();
-    };
+// A printable sheet and the positions of each label on it, in sheet pixels
+struct Sheet {
+    labels: Vec<(i64, i64, RenderedLabel)>,
+}
 
-    // Iterate the labels and place them on the image
-    for (pos, label_info) in label_config.labels.iter().enumerate() {
-        // Generate the Label image, with the cover art, overlaid text and minidisc logo
-        let mut label: RgbImage = ImageBuffer::new(LABEL_WIDTH_PX, LABEL_HEIGHT_PX);
-        image::imageops::overlay(&mut label, &cover_image(&label_info.cover.as_str())?, 0, 0);
-        overlay_minidisc_logo(&mut label)?;
-        label = overlay_text(label,
-            &label_info.title,
-            &label_info.artist,
-            &label_info.release_year,
-        )?;
+// Find the largest scale, no bigger than `scale`, at which `text` is at most `max_length` long.
+// If it's still too long at `min_size` it's left at that and will overflow.
+fn fit_text(
+    text: &str,
+    font: &LabelFont,
+    scale: PxScale,
+    max_length: f32,
+    min_size: f32,
+    orientation: TextOrientation
+) -> PxScale {
+    let mut size = scale.y;
 
-        // Push each label onto the background
-        let x_pos = (pos * LABEL_WIDTH_PX as usize) + (MARGIN as usize * (pos + 2));
-        image::imageops::overlay(&mut printable_area, &label, x_pos as i64, 0);
+    while size > min_size {
+        let length = text::text_length(text, font, PxScale::from(size), orientation);
+        if length <= max_length {
+            break;
+        }
+        // Length is roughly proportional to size, so jump close to the answer then step down
+        // to account for rounding
+        size = (size * max_length / length).min(size - 1.0).max(min_size);
     }
 
-    // Save the final file to disk
-    printable_area.save(args.output)?;
-    Ok(())
-}
\ No newline at end of file
+    PxScale::from(size)
+}
+
+// Break text into lines at word boundaries so that each line is at most `max_length` long. A
+// single word longer than that gets a line to itself.
+fn wrap_text(
+    text: &str,
+    font: &LabelFont,
+    scale: PxScale,
+    max_length: f32,
+    orientation: TextOrientation
+) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", current, word)
+        };
+
+        let length = text::text_length(&candidate, font, scale, orientation);
+        if length > max_length && !current.is_empty() {
+            lines.push(current);
+            current = word.to_string();
+        } else {
+            current = candidate;
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+// Cut text short with an ellipsis so that it's at most `max_length` long
+fn truncate_text(
+    text: &str,
+    font: &LabelFont,
+    scale: PxScale,
+    max_length: f32,
+    orientation: TextOrientation
+) -> String {
+    let fits = |text: &str| text::text_length(text, font, scale, orientation) <= max_length;
+    if fits(text) {
+        return text.to_string();
+    }
+
+    // Not every font has a proper ellipsis character
+    let ellipsis = if font.for_char('\u{2026}').font.glyph_id('\u{2026}').0 != 0 {
+        "\u{2026}"
+    } else {
+        "..."
+    };
+
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let truncated = format!("{}{}", chars.iter().collect::<String>().trim_end(), ellipsis);
+        if fits(&truncated) {
+            return truncated;
+        }
+    }
+    ellipsis.to_string()
+}
+
+// Lay out a field as a block of text starting at size `size`, with lines up to `max_length`
+// long. Fields that may wrap are broken over as many lines as they need. Horizontal lines are
+// stacked down from the top of the block, and columns of rotated or vertical text leftwards from
+// its right edge, with alignment along the direction the text runs. Returns the lines, relative
+// to the top left of the block, and the width and height of the block.
+fn flow_field(
+    field: &TextField,
+    max_length: f32,
+    size: f32,
+    overflow: TextOverflow,
+    orientation: TextOrientation,
+    metrics: &Metrics
+) -> (Vec<TextLine>, (i32, i32)) {
+    let horizontal = orientation == TextOrientation::Horizontal;
+    let font = field.font;
+    let font_scale = PxScale::from(size);
+    let line_advance = (size * LINE_SPACING).round() as i32;
+
+    let wrapped = match overflow {
+        TextOverflow::Wrap if field.wraps =>
+            wrap_text(field.text, font, font_scale, max_length, orientation),
+        TextOverflow::Ellipsis =>
+            vec![truncate_text(field.text, font, font_scale, max_length, orientation)],
+        _ => vec![field.text.to_string()],
+    };
+
+    // The block is as deep as its lines, and as long as the longest line could be
+    let depth = (wrapped.len() as i32 - 1) * line_advance + size.round() as i32;
+    let (width, height) = if horizontal {
+        (max_length.round() as i32, depth)
+    } else {
+        (depth, max_length.round() as i32)
+    };
+
+    let mut lines = Vec::new();
+    let mut next = if horizontal { 0 } else { width - size.round() as i32 };
+    for text in wrapped {
+        let scale = fit_text(&text, font, font_scale, max_length, metrics.min_text_size, orientation);
+
+        let spare_length = max_length - text::text_length(&text, font, scale, orientation);
+        let offset = match field.align {
+            Align::Left => 0,
+            Align::Center => (spare_length / 2.0).round() as i32,
+            Align::Right => spare_length.round() as i32,
+        };
+
+        let (x, y) = if horizontal {
+            // Lines that have been shrunk keep the same baseline as full size text would have
+            let baseline_shift = font.font.as_scaled(font_scale).ascent()
+                - font.font.as_scaled(scale).ascent();
+            (offset, next + baseline_shift.round() as i32)
+        } else {
+            // Columns that have been shrunk are centred on where a full size one would be
+            (next + ((size - scale.y) / 2.0).round() as i32, offset)
+        };
+
+        lines.push(TextLine { text, font: font.clone(), x, y, scale, orientation, color: field.color });
+        next += if horizontal { line_advance } else { -line_advance };
+    }
+    (lines, (width, height))
+}
+
+// Lay out each field as a block of text in `area`, one after another and separated by the
+// padding, starting at size `size`. Horizontal blocks are stacked down from the top of the area,
+// and rotated or vertical ones leftwards from its right edge.
+fn flow_text(
+    fields: &[TextField],
+    area: &TextArea,
+    size: f32,
+    overflow: TextOverflow,
+    orientation: TextOrientation,
+    metrics: &Metrics
+) -> Vec<TextLine> {
+    let horizontal = orientation == TextOrientation::Horizontal;
+    let max_length = if horizontal { area.width } else { area.height } as f32;
+
+    let mut lines = Vec::new();
+    let mut next = if horizontal { area.y } else { area.x + area.width };
+    for field in fields {
+        let (field_lines, (width, height)) =
+            flow_field(field, max_length, size, overflow, orientation, metrics);
+        let (x, y) = if horizontal { (area.x, next) } else { (next - width, area.y) };
+
+        lines.extend(field_lines.into_iter()
+            .map(|line| TextLine { x: x + line.x, y: y + line.y, ..line }));
+        next += if horizontal { height + metrics.padding } else { -(width + metrics.padding) };
+    }
+    lines
+}
+
+// Lay text out at `size`, then at 95% of the size at a time until it fits or it's down to the
+// smallest size text is set at. `lay_out` gives the layout at a size and whether it fits, and the
+// last layout tried is given back along with whether that one fits.
+fn shrink_to_fit<T, E>(
+    size: f32,
+    metrics: &Metrics,
+    mut lay_out: impl FnMut(f32) -> Result<(T, bool), E>
+) -> Result<(T, bool), E> {
+    let mut size = size;
+    loop {
+        let (layout, fits) = lay_out(size)?;
+        if fits || size <= metrics.min_text_size {
+            return Ok((layout, fits));
+        }
+        size = (size * 0.95).max(metrics.min_text_size);
+    }
+}
+
+fn doesnt_fit(label_info: &Label) -> Box<dyn Error> {
+    format!("the text for '{}' by '{}' doesn't fit on the label", label_info.title,
+        label_info.artist
+    ).into()
+}
+
+// Lay out the fields of a label in `area` starting at size `size`. When the text block runs out
+// of the area, all of it is shrunk until it fits.
+fn layout_text(
+    fields: &[TextField],
+    area: &TextArea,
+    size: f32,
+    orientation: TextOrientation,
+    label_info: &Label,
+    context: &RenderContext
+) -> Result<Vec<TextLine>, Box<dyn Error>> {
+    let metrics = &context.metrics;
+
+    let (lines, fits) = shrink_to_fit(size, metrics, |size| {
+        let lines = flow_text(fields, area, size, context.text_overflow, orientation, metrics);
+        let overflows = lines.last().is_some_and(|line| match orientation {
+            TextOrientation::Horizontal => line.y + line.scale.y as i32 > area.y + area.height,
+            _ => line.x < area.x,
+        });
+        Ok::<_, Box<dyn Error>>((lines, !overflows || context.text_overflow != TextOverflow::Wrap))
+    })?;
+    if !fits {
+        return Err(doesnt_fit(label_info));
+    }
+    Ok(lines)
+}
+
+fn overlay_text(label: &RgbImage, lines: &[TextLine]) -> RgbImage {
+    let mut final_label = label.clone();
+    for line in lines {
+        text::draw_line(&mut final_label, line);
+    }
+    final_label
+}
+
+// Load the MiniDisc logo, either from the given path or the copy bundled into the binary
+fn minidisc_logo(path: &Option<String>) -> Result<RgbaImage, Box<dyn Error>> {
+    let md_logo = match path {
+        Some(path) => image::open(fs::canonicalize(path)?)?,
+        None => image::load_from_memory(include_bytes!("../res/minidisc_logo.png"))?,
+    };
+
+    Ok(md_logo.into_rgba8())
+}
+
+// Draw an image with transparency over an opaque one, blending by its alpha channel scaled by
+// `opacity`
+fn overlay_rgba(image: &mut RgbImage, top: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    for (top_x, top_y, pixel) in top.enumerate_pixels() {
+        let (image_x, image_y) = (x + top_x as i64, y + top_y as i64);
+        if image_x < 0 || image_y < 0
+            || image_x >= image.width() as i64 || image_y >= image.height() as i64 {
+            continue;
+        }
+
+        let alpha = pixel[3] as f32 / 255.0 * opacity;
+        let under = image.get_pixel_mut(image_x as u32, image_y as u32);
+        for c in 0..3 {
+            under[c] = (under[c] as f32 * (1.0 - alpha) + pixel[c] as f32 * alpha).round() as u8;
+        }
+    }
+}
+
+// How big the logo is drawn, in pixels
+fn logo_size(settings: &LogoConfig, metrics: &Metrics) -> u32 {
+    settings.size.map_or(metrics.md_logo_size, |size| metrics.px(size).max(1) as u32)
+}
+
+// Where the logo goes when the layout doesn't say. Corners are inset by half the padding from
+// the edges of the label.
+fn logo_anchor(settings: &LogoConfig, metrics: &Metrics) -> (Anchor, (i32, i32)) {
+    let inset = metrics.padding / 2;
+    match settings.position.unwrap_or(LogoPosition::Corner(Corner::BottomRight)) {
+        LogoPosition::Corner(Corner::TopLeft) => (Anchor::new(Position::TopLeft, None), (inset, inset)),
+        LogoPosition::Corner(Corner::TopRight) =>
+            (Anchor::new(Position::TopRight, None), (-inset, inset)),
+        LogoPosition::Corner(Corner::BottomLeft) =>
+            (Anchor::new(Position::BottomLeft, None), (inset, -inset)),
+        LogoPosition::Corner(Corner::BottomRight) =>
+            (Anchor::new(Position::BottomRight, None), (-inset, -inset)),
+        LogoPosition::At { x, y } =>
+            (Anchor::new(Position::TopLeft, None), (metrics.px(x), metrics.px(y))),
+    }
+}
+
+// The record label and catalog number, which go in a line of small text along the bottom of a
+// face label
+fn footer_text(label_info: &Label) -> Option<String> {
+    let footer = [&label_info.record_label, &label_info.catalog_number].into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" \u{00b7} ");
+    (!footer.is_empty()).then_some(footer)
+}
+
+// Lay out the footer in `area`. If the logo is in the way, the line is cut short to stop before
+// it, or starts after it when it's on the left.
+fn layout_footer(
+    footer: &str,
+    area: &Rect,
+    logo: Option<&Rect>,
+    style: &LabelStyle,
+    context: &RenderContext
+) -> Option<TextLine> {
+    let metrics = &context.metrics;
+    let (mut left, mut right) = (area.x, area.right());
+    if let Some(logo) = logo.filter(|logo| logo.y < area.bottom() && logo.bottom() > area.y) {
+        if logo.x + logo.width / 2 > (left + right) / 2 {
+            right = right.min(logo.x - metrics.padding / 2);
+        } else {
+            left = left.max(logo.right() + metrics.padding / 2);
+        }
+    }
+
+    let footer_area = TextArea { x: left, y: area.y, width: right - left, height: area.height };
+    let field = TextField {
+        text: footer, font: &context.font, align: Align::Left, wraps: false,
+        color: style.text_color,
+    };
+    flow_text(&[field], &footer_area, metrics.footer_text_size, TextOverflow::Ellipsis,
+        TextOrientation::Horizontal, metrics
+    ).pop()
+}
+
+// Darken the band of the label that the text is in, across the full width of the label, or the
+// full height for vertical text. The band runs a little past the text on either side.
+fn overlay_scrim<'a>(
+    image: &mut RgbImage,
+    scrim: &ScrimConfig,
+    text: impl Iterator<Item = &'a Rect>,
+    padding: i32
+) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let Some(bounds) = text.copied().reduce(|a, b| {
+        let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+        Rect { x, y, width: a.right().max(b.right()) - x, height: a.bottom().max(b.bottom()) - y }
+    }) else {
+        return;
+    };
+
+    // Text running across the label gets a horizontal band, and text running down it a vertical one
+    let band = if bounds.width >= bounds.height {
+        Rect { x: 0, y: bounds.y - padding / 2, width, height: bounds.height + padding }
+    } else {
+        Rect { x: bounds.x - padding / 2, y: 0, width: bounds.width + padding, height }
+    };
+    let Color(color) = scrim.color.unwrap_or(Color(Rgb([0, 0, 0])));
+    let top = RgbaImage::from_pixel(band.width.max(0) as u32, band.height.max(0) as u32,
+        image::Rgba([color[0], color[1], color[2], 255])
+    );
+    overlay_rgba(image, &top, band.x as i64, band.y as i64,
+        scrim.opacity.unwrap_or(0.5).clamp(0.0, 1.0)
+    );
+}
+
+// Black or white, whichever stands out more against the part of the label under some text
+fn contrasting_color(image: &RgbImage, line: &TextLine) -> Rgb<u8> {
+    let bounds = text::line_bounds(line);
+    let (x0, y0) = (bounds.x.max(0) as u32, bounds.y.max(0) as u32);
+    let x1 = (bounds.right().max(0) as u32).min(image.width());
+    let y1 = (bounds.bottom().max(0) as u32).min(image.height());
+
+    let (mut total, mut count) = (0.0, 0);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            total += color::luminance(*image.get_pixel(x, y));
+            count += 1;
+        }
+    }
+    if count > 0 && total / count as f32 > 0.5 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) }
+}
+
+// Draw the accent strip along the edge of a label, in the color for its genre. Genres are matched
+// ignoring case.
+fn accent_color(label_info: &Label, accent: &AccentConfig) -> Option<CoverColor> {
+    let genre_color = label_info.genre.as_ref().and_then(|genre| accent.genres.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(genre.trim()))
+        .map(|(_, color)| CoverColor::Fixed(*color))
+    );
+    label_info.accent_color.or(genre_color).or(accent.color)
+}
+
+fn overlay_accent(image: &mut RgbImage, style: &LabelStyle, context: &RenderContext) {
+    let accent = &context.accent;
+    let Some(color) = style.accent else {
+        return;
+    };
+
+    let thickness = context.metrics.px(accent.width.unwrap_or(1.0)).max(1) as u32;
+    let (width, height) = image.dimensions();
+    let (x, y, w, h) = match accent.edge.unwrap_or_default() {
+        Edge::Top => (0, 0, width, thickness),
+        Edge::Bottom => (0, height.saturating_sub(thickness), width, thickness),
+        Edge::Left => (0, 0, thickness, height),
+        Edge::Right => (width.saturating_sub(thickness), 0, thickness, height),
+    };
+    for py in y..(y + h).min(height) {
+        for px in x..(x + w).min(width) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+// Draw a QR code for `url`. Modules are a whole number of pixels across so that none of them come
+// out blurred.
+fn qr_code_image(url: &str, metrics: &Metrics) -> Result<RgbImage, Box<dyn Error>> {
+    let code = QrCode::new(url.as_bytes())
+        .map_err(|e| format!("can't make a QR code for '{}': {}", url, e))?;
+    let modules = code.width() as u32 + 2 * QR_QUIET_ZONE;
+    let module_size = (metrics.qr_code_size / modules).max(1);
+    let size = modules * module_size;
+
+    let mut qr_code = RgbImage::from_pixel(size, size, Rgb([255, 255, 255]));
+    for (x, y, pixel) in qr_code.enumerate_pixels_mut() {
+        let module_x = (x / module_size) as i32 - QR_QUIET_ZONE as i32;
+        let module_y = (y / module_size) as i32 - QR_QUIET_ZONE as i32;
+        let width = code.width() as i32;
+        if (0..width).contains(&module_x) && (0..width).contains(&module_y)
+            && code[(module_x as usize, module_y as usize)] == qrcode::Color::Dark {
+            *pixel = Rgb([0, 0, 0]);
+        }
+    }
+    Ok(qr_code)
+}
+
+// Face labels are made up of elements placed by the layout engine. By default the cover goes at
+// the top with the title, artist and year stacked below it, the footer runs along the bottom, the
+// logo goes in a corner and the QR code in the bottom left of the cover. Any of them can be moved
+// with the `[layout]` table of the theme or layout file. The text is shrunk until none of it runs
+// off the bottom of the label or into the footer.
+fn render_face(
+    label_info: &Label,
+    style: &LabelStyle,
+    context: &RenderContext
+) -> Result<RenderedLabel, Box<dyn Error>> {
+    let metrics = &context.metrics;
+    if label_info.cover.is_empty() && label_info.cover_data.is_none() {
+        return Err(format!("the label for '{}' has no cover", label_info.title).into());
+    }
+
+    let (width, height) = (metrics.label_width_px as i32, metrics.label_height_px as i32);
+    let padding = metrics.padding;
+    let source = load_cover(label_info)?;
+    if source.width().min(source.height()) < context.min_cover_size {
+        let name = match label_info.cover_data {
+            Some(_) => format!("the embedded cover for '{}'", label_info.title),
+            None => format!("'{}'", label_info.cover),
+        };
+        let problem = format!(
+            "{} is only {}x{} pixels, smaller than the {} pixels it's printed at, so it will \
+            be blurry",
+            name, source.width(), source.height(), context.min_cover_size
+        );
+        if context.strict {
+            return Err(problem.into());
+        }
+        eprintln!("warning: {}", problem);
+    }
+    let cover = cover_image(&source, label_info.cover_fit, style.background, context)?;
+
+    let logo_settings = label_info.logo.as_ref().map_or(context.logo.clone(),
+        |label_logo| label_logo.or(&context.logo)
+    );
+    let md_logo = context.md_logo.as_ref()
+        .filter(|_| logo_settings.enabled.unwrap_or(true))
+        .map(|md_logo| {
+            let size = logo_size(&logo_settings, metrics);
+            image::imageops::resize(md_logo, size, size, image::imageops::FilterType::CatmullRom)
+        });
+    let qr_code = label_info.url.as_deref().map(|url| qr_code_image(url, metrics)).transpose()?;
+    let footer = footer_text(label_info);
+
+    let field = |name, text, font, align: Option<Align>, wraps| (name, TextField {
+        text, font, align: align.unwrap_or(context.align), wraps, color: style.text_color
+    });
+    let (title, artist) = (style.case(&label_info.title), style.case(&label_info.artist));
+    let year = label_info.release_year.as_deref().map(|year| style.case(year));
+    let mut fields = vec![
+        field("title", &title, &style.fonts.title, label_info.title_align, true),
+        field("artist", &artist, &style.fonts.artist, label_info.artist_align, true),
+    ];
+    if let Some(year) = &year {
+        fields.push(field("year", year, &style.fonts.year, label_info.year_align, false));
+    }
+
+    // By default, text goes in a column below the cover. It can run down to just above the
+    // bottom edge of the label, or the footer if it hasn't been moved.
+    let orientation = label_info.text_orientation.unwrap_or_default();
+    let horizontal = orientation == TextOrientation::Horizontal;
+    let cover_area = context.cover_area;
+    let text_top = cover_area.bottom() + padding;
+    let footer_height = metrics.footer_text_size.round() as i32;
+    let text_bottom = match footer {
+        Some(_) if !context.layout.contains_key("footer") => height - padding - footer_height,
+        _ => height - padding / 2,
+    };
+    let text_area = context.text_area.unwrap_or(Rect {
+        x: padding, y: text_top, width: width - 2 * padding, height: text_bottom - text_top
+    });
+    let max_length = if horizontal { text_area.width } else { text_area.height };
+
+    let inside = |position| Anchor::new(position, None);
+    let mut defaults = vec![("cover", inside(Position::TopLeft), (cover_area.x, cover_area.y))];
+    if footer.is_some() {
+        defaults.push(("footer", inside(Position::BottomLeft), (padding, -padding / 2)));
+    }
+    if md_logo.is_some() {
+        let (anchor, offset) = logo_anchor(&logo_settings, metrics);
+        defaults.push(("logo", anchor, offset));
+    }
+    if qr_code.is_some() {
+        let anchor = Anchor::new(Position::BottomLeft, Some("cover"));
+        defaults.push(("qr-code", anchor, (padding / 2, -padding / 2)));
+    }
+    for (i, (name, _)) in fields.iter().enumerate() {
+        defaults.push(match (i, horizontal) {
+            (0, true) => (name, inside(Position::TopLeft), (text_area.x, text_area.y)),
+            (0, false) =>
+                (name, inside(Position::TopRight), (text_area.right() - width, text_area.y)),
+            (_, true) => (name, Anchor::new(Position::Below, Some(fields[i - 1].0)), (0, padding)),
+            (_, false) =>
+                (name, Anchor::new(Position::LeftOf, Some(fields[i - 1].0)), (-padding, 0)),
+        });
+    }
+
+    let sizes: HashMap<&str, (i32, i32)> = HashMap::from([
+        ("cover", (cover.width() as i32, cover.height() as i32)),
+        ("footer", (width - 2 * padding, footer_height)),
+        ("logo", md_logo.as_ref().map_or((0, 0), |logo| (logo.width() as i32, logo.height() as i32))),
+        ("qr-code", qr_code.as_ref().map_or((0, 0), |qr| (qr.width() as i32, qr.height() as i32))),
+    ]);
+
+    let ((mut rects, text), fits) = shrink_to_fit(style.text_size, metrics, |size| {
+        let flowed: Vec<_> = fields.iter()
+            .map(|(_, field)| flow_field(
+                field, max_length as f32, size, context.text_overflow, orientation, metrics
+            ))
+            .collect();
+
+        let elements: Vec<Element> = defaults.iter()
+            .map(|(name, anchor, offset)| {
+                let (width, height) = sizes.get(name).copied().unwrap_or_else(|| {
+                    let i = fields.iter().position(|(field, _)| field == name).unwrap_or(0);
+                    flowed[i].1
+                });
+                let (anchor, offset) = match context.layout.get(*name) {
+                    Some(placement) => (placement.anchor.clone(),
+                        (metrics.px(placement.offset[0]), metrics.px(placement.offset[1]))),
+                    None => (anchor.clone(), *offset),
+                };
+                Element { name, width, height, anchor, offset }
+            })
+            .collect();
+        let rects = layout::resolve(&elements, Rect { x: 0, y: 0, width, height })
+            .map_err(|e| format!("the label for '{}': {}", label_info.title, e))?;
+
+        let footer_rect = rects.get("footer");
+        let overflows = fields.iter().any(|(name, _)| {
+            let rect = &rects[*name];
+            let off_label = if horizontal {
+                rect.bottom() > text_area.bottom()
+            } else {
+                rect.x < text_area.x
+            };
+            off_label || footer_rect.is_some_and(|footer| footer.intersects(rect))
+        });
+
+        let text: Vec<TextLine> = fields.iter().zip(flowed)
+            .flat_map(|((name, _), (lines, _))| {
+                let rect = rects[*name];
+                lines.into_iter().map(move |line| TextLine {
+                    x: rect.x + line.x, y: rect.y + line.y, ..line
+                })
+            })
+            .collect();
+        Ok::<_, Box<dyn Error>>(((rects, text), !overflows
+            || context.text_overflow != TextOverflow::Wrap))
+    })?;
+    if !fits {
+        return Err(doesnt_fit(label_info));
+    }
+    let mut text = text;
+    rects.insert("text-area".to_string(), text_area);
+
+    // Put the label together, with the text kept separate from the artwork
+    let cover_rect = rects["cover"];
+    let mut art = match context.cover_layout {
+        CoverLayout::Blurred => blurred_background(&source, metrics)?,
+        _ => style.background_image(width as u32, height as u32, cover_rect.bottom()),
+    };
+    image::imageops::overlay(&mut art, &cover, cover_rect.x as i64, cover_rect.y as i64);
+    if let Some(scrim) = &style.scrim {
+        overlay_scrim(&mut art, scrim, fields.iter().map(|(name, _)| &rects[*name]), padding);
+    }
+    if let (Some(md_logo), Some(rect)) = (&md_logo, rects.get("logo")) {
+        overlay_rgba(&mut art, md_logo, rect.x as i64, rect.y as i64,
+            logo_settings.opacity.unwrap_or(1.0).clamp(0.0, 1.0)
+        );
+    }
+    if let (Some(qr_code), Some(rect)) = (&qr_code, rects.get("qr-code")) {
+        image::imageops::overlay(&mut art, qr_code, rect.x as i64, rect.y as i64);
+    }
+    if let (Some(footer), Some(rect)) = (&footer, rects.get("footer")) {
+        text.extend(layout_footer(footer, rect, rects.get("logo"), style, context));
+    }
+
+    // Tracks can go in a smaller font in whatever room is left below the text
+    if label_info.tracks_on_face.unwrap_or(false) && !label_info.tracks.is_empty() {
+        let block_bottom = fields.iter().map(|(name, _)| rects[*name].bottom()).max()
+            .unwrap_or(text_area.y);
+        let tracks_area = TextArea {
+            x: text_area.x,
+            y: block_bottom + padding / 2,
+            width: text_area.width,
+            height: text_area.bottom() - block_bottom - padding / 2,
+        };
+        if (tracks_area.height as f32) < metrics.min_text_size {
+            return Err(format!(
+                "there's no room for the tracks of '{}' on the label", label_info.title
+            ).into());
+        }
+        text.extend(layout_tracks(
+            &label_info.tracks, &tracks_area, metrics.track_text_size, style, context
+        ));
+    }
+    Ok(RenderedLabel { art, text, elements: rects, bleed: 0 })
+}
+
+// Spines have the artist and title as a single line of text running along the strip. It's
+// rotated to read from top to bottom unless the label asks for vertical text.
+fn render_spine(
+    label_info: &Label,
+    style: &LabelStyle,
+    context: &RenderContext
+) -> Result<RenderedLabel, Box<dyn Error>> {
+    let metrics = &context.metrics;
+    let art = style.background_image(metrics.spine_width_px, metrics.label_height_px, 0);
+
+    let text = style.case(&format!("{} \u{2014} {}", label_info.artist, label_info.title));
+    let fields = [TextField {
+        text: &text,
+        font: &style.fonts.title,
+        align: label_info.title_align.unwrap_or(context.align),
+        wraps: false,
+        color: style.text_color,
+    }];
+
+    // A single column down the middle of the strip
+    let size = metrics.spine_text_size;
+    let area = TextArea {
+        x: ((metrics.spine_width_px as f32 - size) / 2.0).round() as i32,
+        y: metrics.padding,
+        width: size.round() as i32,
+        height: metrics.label_height_px as i32 - 2 * metrics.padding,
+    };
+
+    let orientation = label_info.text_orientation.unwrap_or(TextOrientation::Rotated);
+    let text = layout_text(&fields, &area, size, orientation, label_info, context)?;
+    let text_area = Rect { x: area.x, y: area.y, width: area.width, height: area.height };
+    let elements = HashMap::from([("text-area".to_string(), text_area)]);
+    Ok(RenderedLabel { art, text, elements, bleed: 0 })
+}
+
+// Lay out a numbered track list in `area`, split into balanced columns, with any durations
+// right aligned in their column. The text is set at the largest size, no bigger than `size`, at
+// which every track fits on a single line of its column, and any titles that are still too long
+// at the minimum size are cut short.
+fn layout_tracks(
+    tracks: &[Track],
+    area: &TextArea,
+    size: f32,
+    style: &LabelStyle,
+    context: &RenderContext
+) -> Vec<TextLine> {
+    let (font, metrics) = (&context.font, &context.metrics);
+    let entries: Vec<(String, Option<&str>)> = tracks.iter().enumerate()
+        .map(|(n, track)| (format!("{}. {}", n + 1, track.title()), track.duration()))
+        .collect();
+    if entries.is_empty() {
+        return Vec::new();
+    }
+
+    let orientation = TextOrientation::Horizontal;
+    let gap = metrics.padding as f32;
+
+    // Titles get whatever room is left in the column after their duration
+    let length = |text: &str, scale| text::text_length(text, font, scale, orientation);
+    let title_width = |duration: Option<&str>, column_width: f32, scale| {
+        column_width - duration.map_or(0.0, |duration| length(duration, scale) + gap / 2.0)
+    };
+
+    // Use as few columns as it takes to fit every row in, then spread the tracks evenly between
+    // them. Laying the tracks out can't fail, so neither can fitting them.
+    let Ok(((size, rows, column_width), _)) = shrink_to_fit(size, metrics, |size| {
+        let line_advance = size * LINE_SPACING;
+        let max_rows = ((area.height as f32 - size) / line_advance).floor().max(0.0) as usize + 1;
+        let columns = entries.len().div_ceil(max_rows);
+        let rows = entries.len().div_ceil(columns);
+        let column_width = (area.width as f32 - gap * (columns - 1) as f32) / columns as f32;
+
+        let scale = PxScale::from(size);
+        let fits = entries.iter().all(|(title, duration)| {
+            length(title, scale) <= title_width(*duration, column_width, scale)
+        });
+        Ok::<_, std::convert::Infallible>(((size, rows, column_width), fits))
+    });
+
+    let (scale, line_advance) = (PxScale::from(size), size * LINE_SPACING);
+    let mut lines = Vec::new();
+    for (i, (title, duration)) in entries.iter().enumerate() {
+        let x = area.x as f32 + (i / rows) as f32 * (column_width + gap);
+        let y = area.y + ((i % rows) as f32 * line_advance).round() as i32;
+        let line = |text: String, x: f32| TextLine {
+            text, font: font.clone(), x: x.round() as i32, y, scale, orientation,
+            color: style.text_color
+        };
+
+        let width = title_width(*duration, column_width, scale);
+        lines.push(line(truncate_text(title, font, scale, width, orientation), x));
+        if let Some(duration) = duration {
+            lines.push(line(duration.to_string(), x + column_width - length(duration, scale)));
+        }
+    }
+    lines
+}
+
+// Backs have the artist and title as a heading across the top, with the track list below and the
+// barcode, if there is one, underneath that
+fn render_back(
+    label_info: &Label,
+    style: &LabelStyle,
+    context: &RenderContext
+) -> Result<RenderedLabel, Box<dyn Error>> {
+    let metrics = &context.metrics;
+    let padding = metrics.padding;
+    let mut art = style.background_image(metrics.back_width_px, metrics.label_height_px, 0);
+
+    let heading = style.case(&format!("{} \u{2014} {}", label_info.artist, label_info.title));
+    let fields = [TextField {
+        text: &heading,
+        font: &style.fonts.title,
+        align: label_info.title_align.unwrap_or(context.align),
+        wraps: false,
+        color: style.text_color,
+    }];
+    let width = metrics.back_width_px as i32 - 2 * padding;
+    let orientation = TextOrientation::Horizontal;
+
+    // The running time goes at the right hand end of the heading, which gets whatever's left. It's
+    // set at the size the heading ends up at, so that the two shrink together.
+    let runtime_length = |runtime: &str, scale: PxScale| {
+        text::text_length(runtime, &style.fonts.title, scale, orientation).round() as i32
+    };
+    let heading_width = label_info.runtime.as_ref().map_or(width, |runtime| {
+        width - runtime_length(runtime, PxScale::from(style.text_size)) - padding
+    });
+
+    let heading_area = TextArea {
+        x: padding, y: padding, width: heading_width, height: style.text_size as i32
+    };
+    let mut text = flow_text(&fields, &heading_area, style.text_size, context.text_overflow,
+        orientation, metrics
+    );
+    let (y, scale) = text.first()
+        .map_or((padding, PxScale::from(style.text_size)), |line| (line.y, line.scale));
+    text.extend(label_info.runtime.as_ref().map(|runtime| TextLine {
+        text: runtime.clone(), font: style.fonts.title.clone(),
+        x: padding + width - runtime_length(runtime, scale), y, scale, orientation,
+        color: style.text_color
+    }));
+
+    let mut bottom = metrics.label_height_px as i32 - padding;
+    if let Some(code) = &label_info.barcode {
+        let barcode = barcode::draw_barcode(
+            code, &context.font, metrics.barcode_width, metrics.barcode_height
+        ).map_err(|e| format!("the label for '{}': {}", label_info.title, e))?;
+        bottom -= barcode.height() as i32;
+        let x = metrics.back_width_px as i32 - padding - barcode.width() as i32;
+        image::imageops::overlay(&mut art, &barcode, x as i64, bottom as i64);
+        bottom -= padding;
+    }
+
+    let top = heading_area.y + heading_area.height + padding;
+    let tracks_area = TextArea {
+        x: padding,
+        y: top,
+        width,
+        height: bottom - top,
+    };
+    text.extend(layout_tracks(
+        &label_info.tracks, &tracks_area, metrics.track_text_size, style, context
+    ));
+
+    let text_area = Rect {
+        x: padding, y: padding, width, height: tracks_area.y + tracks_area.height - padding
+    };
+    let elements = HashMap::from([("text-area".to_string(), text_area)]);
+    Ok(RenderedLabel { art, text, elements, bleed: 0 })
+}
+
+fn render_label(
+    label_info: &Label,
+    label_type: LabelType,
+    context: &RenderContext
+) -> Result<RenderedLabel, Box<dyn Error>> {
+    let field_font = |path: &Option<String>, default: &LabelFont| match path {
+        Some(path) => LabelFont::from_file(path)
+            .map(|font| font.with_fallbacks(&context.font.fallbacks)),
+        None => Ok(default.clone()),
+    };
+    let fonts = LabelFonts {
+        title: field_font(&label_info.title_font, &context.fonts.title)?,
+        artist: field_font(&label_info.artist_font, &context.fonts.artist)?,
+        year: field_font(&label_info.year_font, &context.fonts.year)?,
+    };
+
+    let metrics = &context.metrics;
+    let label_style = &label_info.style;
+    let background = label_style.background.unwrap_or(context.background);
+    let text_color = label_style.text_color.unwrap_or(context.text_color);
+    let accent = accent_color(label_info, &context.accent);
+
+    // Colors can be taken from the cover, so long as there is one
+    let from_cover = [Some(background), accent, match text_color {
+        TextColor::Fixed(color) => Some(color),
+        TextColor::Auto => None,
+    }];
+    let has_cover = !label_info.cover.is_empty() || label_info.cover_data.is_some();
+    let palette = if from_cover.iter().flatten().any(CoverColor::is_from_cover) && has_cover {
+        Some(palette::extract(&load_cover(label_info)?))
+    } else {
+        None
+    };
+
+    let style = LabelStyle {
+        fonts,
+        background: background.resolve(palette.as_ref(), BACKGROUND_COLOR),
+        text_color: match text_color {
+            TextColor::Fixed(color) => color.resolve(palette.as_ref(), TEXT_COLOR),
+            TextColor::Auto => TEXT_COLOR,
+        },
+        auto_text_color: text_color == TextColor::Auto,
+        text_size: label_style.text_size.map_or(metrics.text_size, |size| size * metrics.dpmm),
+        uppercase: label_style.uppercase.unwrap_or(false),
+        scrim: label_style.scrim.clone().or(context.scrim.clone())
+            .filter(|scrim| scrim.enabled.unwrap_or(true)),
+        // There's no strip at all when it should match a cover that isn't there
+        accent: accent.filter(|accent| !accent.is_from_cover() || palette.is_some())
+            .map(|accent| accent.resolve(palette.as_ref(), BACKGROUND_COLOR)),
+        gradient: label_style.gradient.clone().or(context.gradient.clone()),
+    };
+
+    let mut label = match label_type {
+        LabelType::Face => render_face(label_info, &style, context),
+        LabelType::Spine => render_spine(label_info, &style, context),
+        LabelType::Back => render_back(label_info, &style, context),
+    }?;
+    overlay_accent(&mut label.art, &style, context);
+    if style.auto_text_color {
+        for line in &mut label.text {
+            line.color = contrasting_color(&label.art, line);
+        }
+    }
+
+    let own_type = label_info.label_type.unwrap_or_default();
+    let free_lines: Vec<_> = label_info.text.iter()
+        .filter(|free_text| free_text.label_type.unwrap_or(own_type) == label_type)
+        .map(|free_text| (free_text.color.is_none(), TextLine {
+            text: free_text.content.clone(),
+            font: context.font.clone(),
+            x: metrics.px(free_text.x),
+            y: metrics.px(free_text.y),
+            scale: PxScale::from(free_text.size
+                .map_or(metrics.track_text_size, |size| size * metrics.dpmm)),
+            orientation: TextOrientation::Horizontal,
+            color: free_text.color.map_or(style.text_color, |Color(color)| color),
+        }))
+        .collect();
+    for (styled, mut line) in free_lines {
+        if styled && style.auto_text_color {
+            line.color = contrasting_color(&label.art, &line);
+        }
+        label.text.push(line);
+    }
+    Ok(label)
+}
+
+// Work out where labels of the given sizes go on sheets, packing them in to use as few sheets as
+// possible. Each sheet starts as one free space, inside the sheet margin around its edges, and
+// every label is cut out of the top left of the highest free space that it fits in on the first
+// sheet with room for it. What's left is split into the space to the right of the label, which
+// runs the rest of the way down, and the space below it. Labels are kept apart by the margin.
+//
+// Labels of the same size end up in rows, in the order they're given, and smaller labels like
+// spines fill in the gaps left beside and below bigger ones, even on earlier sheets. Each
+// position is the sheet the label is on and where it is on that sheet.
+fn pack_positions(sizes: &[(i64, i64)], metrics: &Metrics) -> Vec<(usize, i64, i64)> {
+    let margin = metrics.margin as i64;
+    let [top, right, bottom, left] = metrics.sheet_margin.map(|edge| edge as i64);
+    let sheet_width = metrics.printable_width_px as i64 - left - right;
+    let sheet_height = metrics.printable_height_px as i64 - top - bottom;
+    // Free spaces are (x, y, width, height), with room for the margin after the last label
+    let empty_sheet = vec![(left, top, sheet_width + margin, sheet_height + margin)];
+    let mut free: Vec<Vec<(i64, i64, i64, i64)>> = Vec::new();
+    let mut positions = Vec::new();
+
+    for &(width, height) in sizes {
+        let (width, height) = (width + margin, height + margin);
+        let space = free.iter().enumerate().find_map(|(sheet, spaces)| {
+            spaces.iter().enumerate()
+                .filter(|(_, space)| space.2 >= width && space.3 >= height)
+                .min_by_key(|(_, space)| (space.1, space.0))
+                .map(|(i, _)| (sheet, i))
+        });
+        // A label too big for an empty sheet still goes on one of its own
+        let (sheet, i) = space.unwrap_or_else(|| {
+            free.push(empty_sheet.clone());
+            (free.len() - 1, 0)
+        });
+
+        let (x, y, space_width, space_height) = free[sheet].swap_remove(i);
+        let right = (x + width, y, space_width - width, space_height);
+        let below = (x, y + height, width.min(space_width), space_height - height);
+        free[sheet].extend([right, below].into_iter().filter(|space| space.2 > 0 && space.3 > 0));
+        positions.push((sheet, x, y));
+    }
+    positions
+}
+
+// Pack labels onto as few sheets as it takes. When they can be rotated they're all turned
+// sideways if that fits them on fewer sheets, eg. on A4 where more rows of landscape labels fit
+// down the page than columns of portrait ones across it. Labels can be packed onto earlier sheets
+// than the ones before them, so this also gives the order they end up in across the sheets, as
+// indexes into `labels`.
+fn place_labels(
+    labels: Vec<RenderedLabel>,
+    metrics: &Metrics,
+    pack: Pack
+) -> (Vec<Sheet>, Vec<usize>) {
+    let sizes: Vec<_> = labels.iter()
+        .map(|label| (label.art.width() as i64, label.art.height() as i64))
+        .collect();
+    let sheet_count = |positions: &[(usize, i64, i64)]| {
+        positions.last().map_or(0, |(sheet, _, _)| sheet + 1)
+    };
+
+    let mut positions = pack_positions(&sizes, metrics);
+    let mut turn = false;
+    if pack == Pack::Rotate {
+        let turned: Vec<_> = sizes.iter().map(|&(width, height)| (height, width)).collect();
+        let turned_positions = pack_positions(&turned, metrics);
+        if sheet_count(&turned_positions) < sheet_count(&positions) {
+            (positions, turn) = (turned_positions, true);
+        }
+    }
+
+    let mut sheets: Vec<Sheet> = (0..sheet_count(&positions))
+        .map(|_| Sheet { labels: Vec::new() })
+        .collect();
+    let mut order: Vec<usize> = (0..labels.len()).collect();
+    order.sort_by_key(|&i| positions[i].0);
+    for (label, (sheet, x, y)) in labels.into_iter().zip(positions) {
+        sheets[sheet].labels.push((x, y, if turn { label.turned() } else { label }));
+    }
+    (sheets, order)
+}
+
+// Flatten a sheet into a single image, drawing each label and its text onto a background the
+// same size as a Zink printable sticker. Any guide marks go under the labels
+fn rasterize_sheet(
+    sheet: &Sheet,
+    metrics: &Metrics,
+    color: Rgb<u8>,
+    marks: &[Rect]
+) -> Result<RgbImage, Box<dyn Error>> {
+    let mut printable_area: RgbImage =
+        ImageBuffer::from_pixel(metrics.printable_width_px, metrics.printable_height_px, color);
+    guides::draw(&mut printable_area, marks);
+
+    for (x, y, label) in &sheet.labels {
+        image::imageops::overlay(&mut printable_area, &overlay_text(&label.art, &label.text), *x, *y);
+    }
+    Ok(printable_area)
+}
+
+// The details of a label that can be used in file names
+fn name_fields(label_info: &Label, label_type: LabelType) -> [(&'static str, String); 4] {
+    [
+        ("artist", label_info.artist.clone()),
+        ("title", label_info.title.clone()),
+        ("year", label_info.release_year.clone().unwrap_or_default()),
+        ("type", format!("{:?}", label_type).to_lowercase()),
+    ]
+}
+
+// Work out where to write a sheet. A single sheet is written to the output path as given,
+// otherwise each sheet is named using the sheet pattern. Either can name the sheet after the
+// first label on it.
+fn sheet_path(
+    output: &str,
+    pattern: &str,
+    sheet: usize,
+    sheet_count: usize,
+    fields: &[(&str, String)]
+) -> PathBuf {
+    let output = Path::new(output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = output.extension().unwrap_or_default().to_string_lossy();
+    let name = if sheet_count == 1 {
+        output.file_name().unwrap_or_default().to_string_lossy().to_string()
+    } else {
+        pattern.replace("{stem}", &stem).replace("{ext}", &ext)
+    };
+
+    // `{n}` is the old name for `{num}`, which clap can't show in help text
+    let number = (sheet + 1).to_string();
+    let name = name.replace("{num}", &number).replace("{n}", &number);
+    output.with_file_name(output::fill_template(&name, fields))
+}
+
+// Parse a sheet size, given by name or as a `WIDTHxHEIGHT` size in mm
+fn parse_sheet_size(size: &str) -> Result<(f32, f32), Box<dyn Error>> {
+    let name = size.trim().to_lowercase();
+    match SHEET_SIZES.iter().find(|(sheet, _)| *sheet == name) {
+        Some((_, size)) => Ok(*size),
+        None => parse_page_size(name.strip_suffix("mm").unwrap_or(&name)).map_err(|_| {
+            let names = SHEET_SIZES.map(|(name, _)| name).join(", ");
+            LabelError::Config(format!("invalid sheet size '{}', expected one of {} or \
+                WIDTHxHEIGHT in mm with both more than 0", size, names)).into()
+        }),
+    }
+}
+
+// Parse a sheet margin, given as one size in mm for every edge or as `TOP,RIGHT,BOTTOM,LEFT`
+fn parse_sheet_margin(margin: &str) -> Result<SheetMargin, Box<dyn Error>> {
+    let invalid = || format!(
+        "invalid sheet margin '{}', expected a size in mm or TOP,RIGHT,BOTTOM,LEFT", margin
+    );
+    let sizes = margin.split(',')
+        .map(|size| size.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    match sizes[..] {
+        [all] => Ok(SheetMargin::All(all)),
+        [top, right, bottom, left] => Ok(SheetMargin::Edges([top, right, bottom, left])),
+        _ => Err(invalid().into()),
+    }
+}
+
+// Parse a `WIDTHxHEIGHT` size in mm, eg. `76x50`. Both have to be more than 0.
+fn parse_page_size(size: &str) -> Result<(f32, f32), Box<dyn Error>> {
+    let invalid = || LabelError::Config(format!(
+        "invalid page size '{}', expected WIDTHxHEIGHT in mm with both more than 0", size
+    ));
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let length = |mm: &str| mm.trim().parse::<f32>().ok().filter(|mm| mm.is_finite() && *mm > 0.0);
+    match (length(width), length(height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(invalid().into()),
+    }
+}
+
+// Read a layout file, or the layout piped in on stdin when it's given as `-`
+fn read_layout(path: &str) -> Result<String, Box<dyn Error>> {
+    if path == "-" {
+        let mut layout = String::new();
+        io::stdin().read_to_string(&mut layout).map_err(|source| {
+            LabelError::MissingFile { what: "the layout from stdin".to_string(), source }
+        })?;
+        return Ok(layout);
+    }
+    Ok(fs::read_to_string(path).map_err(|source| {
+        LabelError::MissingFile { what: format!("layout '{}'", path), source }
+    })?)
+}
+
+// The languages a layout can be written in. They all describe the same settings, with the same
+// names, eg. `{"labels": [{"artist": "YOB", "title": "Atma"}]}` in JSON.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LayoutFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl LayoutFormat {
+    // Layouts are told apart by their extension. Anything that isn't YAML or JSON is read as TOML,
+    // including layouts piped in on stdin
+    fn from_path(path: &str) -> LayoutFormat {
+        let extension = Path::new(path).extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("yaml" | "yml") => LayoutFormat::Yaml,
+            Some("json") => LayoutFormat::Json,
+            _ => LayoutFormat::Toml,
+        }
+    }
+}
+
+// Read a layout file. Anything under `[defaults]`, whether it's the content of a label like the
+// artist or year, or how it's styled, is copied into every `[[labels]]` entry that doesn't set it
+// itself, so it only needs writing once for a sheet of labels from the same series.
+fn parse_config(layout: &str, format: LayoutFormat) -> Result<Config, Box<dyn Error>> {
+    let table = merge_defaults(layout, format).map_err(error::config)?;
+    Ok(table.try_into().map_err(|e: toml::de::Error| LabelError::Config(e.to_string()))?)
+}
+
+// Read a layout into a table, with its `[defaults]` merged into each label
+fn merge_defaults(layout: &str, format: LayoutFormat) -> Result<toml::Table, Box<dyn Error>> {
+    let mut table: toml::Table = match format {
+        LayoutFormat::Toml => toml::from_str(layout)?,
+        LayoutFormat::Yaml => serde_yaml::from_str(layout)?,
+        LayoutFormat::Json => serde_json::from_str(layout)?,
+    };
+    let defaults = match table.remove("defaults") {
+        Some(toml::Value::Table(defaults)) => defaults,
+        Some(_) => return Err("`defaults` must be a table of label settings".into()),
+        None => toml::Table::new(),
+    };
+    if let Some(toml::Value::Array(labels)) = table.get_mut("labels") {
+        for label in labels.iter_mut().filter_map(toml::Value::as_table_mut) {
+            for (key, value) in &defaults {
+                label.entry(key.as_str()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    Ok(table)
+}
+
+// The theme a layout is drawn in, which can be picked on the command line or in the layout
+fn load_theme(args: &RenderArgs, label_config: &Config) -> Result<Theme, Box<dyn Error>> {
+    match args.theme.as_ref().or(label_config.theme.as_ref()) {
+        Some(name) => theme::load(name),
+        None => Ok(Theme::default()),
+    }
+}
+
+// Gather everything labels are drawn with, from the command line, the layout and its theme
+fn render_context(
+    args: &RenderArgs,
+    label_config: &Config,
+    theme: &Theme
+) -> Result<RenderContext, Box<dyn Error>> {
+    let mut metrics = match args.dpi.or(label_config.dpi) {
+        Some(dpi) => Metrics::from_dpi(dpi),
+        None => Metrics::new(DESIRED_DPMM as f32),
+    }.with_theme(theme);
+    if let Some(sheet) = args.sheet {
+        metrics = metrics.with_sheet_size(sheet.template().page);
+    }
+    if let Some(size) = &args.sheet_size {
+        metrics = metrics.with_sheet_size(parse_sheet_size(size)?);
+    }
+    if let Some(min_text_size) = label_config.min_text_size {
+        metrics.min_text_size = min_text_size * metrics.dpmm;
+    }
+    if let Some(padding) = args.padding.or(label_config.padding) {
+        metrics.padding = metrics.px(padding).max(0);
+    }
+    if let Some(margin) = args.margin.or(label_config.margin) {
+        metrics.margin = metrics.px(margin).max(0);
+    }
+    let sheet_margin = match &args.sheet_margin {
+        Some(margin) => Some(parse_sheet_margin(margin)?),
+        None => label_config.sheet_margin,
+    };
+    metrics.sheet_margin = match sheet_margin {
+        Some(margin) => margin.edges().map(|mm| metrics.px(mm).max(0)),
+        None => [0, 0, 0, 2 * metrics.margin],
+    };
+    let theme_geometry = theme.label.clone().unwrap_or_default();
+    let mut geometry = label_config.label.as_ref()
+        .map_or(theme_geometry.clone(), |geometry| geometry.or(&theme_geometry));
+    if let Some(disc) = args.disc {
+        let (width, height) = disc.label_size();
+        (geometry.width_mm, geometry.height_mm) = (Some(width), Some(height));
+    }
+    let metrics = metrics.with_label_size(&geometry);
+    let label_mm = (
+        geometry.width_mm.unwrap_or(LABEL_WIDTH as f32),
+        geometry.height_mm.unwrap_or(LABEL_HEIGHT as f32),
+    );
+    let cover_area = match geometry.cover_area {
+        Some(area) => area.px("cover area", label_mm, &metrics)?,
+        None => Rect {
+            x: 0,
+            y: 0,
+            width: metrics.label_width_px as i32,
+            height: metrics.label_width_px.min(metrics.label_height_px) as i32,
+        },
+    };
+    let text_area = geometry.text_area
+        .map(|area| area.px("text area", label_mm, &metrics))
+        .transpose()?;
+
+    let font = match &args.font {
+        Some(name) => LabelFont::system(name).unwrap_or_else(|| {
+            eprintln!("warning: no installed font matches '{}', using the bundled font", name);
+            LabelFont::bundled()
+        }),
+        None => match &theme.font {
+            Some(name) => LabelFont::load(name)?,
+            None => LabelFont::bundled(),
+        },
+    };
+    let fallback_fonts = theme.fallback_fonts.iter()
+        .chain(&label_config.fallback_fonts)
+        .chain(&args.fallback_font)
+        .map(|name| LabelFont::load(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let font = font.with_fallbacks(&fallback_fonts);
+
+    // The theme's fonts for each line are only used when one isn't given on the command line
+    let theme_font = |name: &Option<String>| match name {
+        Some(name) if args.font.is_none() => LabelFont::load(name)
+            .map(|field_font| field_font.with_fallbacks(&fallback_fonts)),
+        _ => Ok(font.clone()),
+    };
+    let fonts = LabelFonts {
+        title: theme_font(&theme.title_font)?,
+        artist: theme_font(&theme.artist_font)?,
+        year: theme_font(&theme.year_font)?,
+    };
+
+    // Settings in the layout take precedence over the theme
+    let mut layout = theme.layout.clone();
+    layout.extend(label_config.layout.clone());
+    if let Some(name) = layout.keys().find(|name| !FACE_ELEMENTS.contains(&name.as_str())) {
+        return Err(format!("there's no element called '{}' to lay out, the elements are {}",
+            name, FACE_ELEMENTS.join(", ")).into());
+    }
+    let theme_logo = theme.logo.clone().unwrap_or_default();
+    Ok(RenderContext {
+        md_logo: if args.no_logo { None } else { Some(minidisc_logo(&args.logo)?) },
+        logo: label_config.logo.as_ref().map_or(theme_logo.clone(), |logo| logo.or(&theme_logo)),
+        accent: label_config.accent.clone().or(theme.accent.clone()).unwrap_or_default(),
+        font,
+        fonts,
+        cover_layout: theme.cover_layout.unwrap_or_default(),
+        cover_area,
+        text_area,
+        layout,
+        background: theme.background.unwrap_or(CoverColor::Fixed(Color(BACKGROUND_COLOR))),
+        text_color: theme.text_color
+            .unwrap_or(TextColor::Fixed(CoverColor::Fixed(Color(TEXT_COLOR)))),
+        scrim: theme.scrim.clone(),
+        gradient: theme.gradient.clone(),
+        align: theme.align.unwrap_or_default(),
+        text_overflow: label_config.text_overflow.or(theme.text_overflow).unwrap_or_default(),
+        min_cover_size: label_config.min_cover_size.unwrap_or(metrics.label_width_px),
+        resize_filter: label_config.resize_filter.unwrap_or_default(),
+        sharpen: label_config.sharpen.clone(),
+        strict: args.strict,
+        metrics,
+    })
+}
+
+// The labels made for each entry in a layout. The labels for a disc are packed in one after the
+// other, so they're next to each other where there's room. Zink sheets aren't wide enough for
+// all three, so the back of a set goes on the next sheet, and later spines fill the gaps
+fn label_types(label_info: &Label, set: LabelSet) -> Vec<LabelType> {
+    match set {
+        LabelSet::Single => vec![label_info.label_type.unwrap_or_default()],
+        LabelSet::Full => vec![LabelType::Face, LabelType::Spine, LabelType::Back],
+    }
+}
+
+// Make a label for a single disc. Its details can be filled in from the tags of audio files, a
+// CUE sheet or a playlist, with anything given on the command line taking precedence
+fn generate(args: &GenerateArgs) -> Result<(), Box<dyn Error>> {
+    let imported = if let Some(path) = &args.from_audio {
+        Some(audio::label_from_audio(path)?)
+    } else if let Some(path) = &args.cue {
+        Some(cue::label_from_cue(path)?)
+    } else if let Some(path) = &args.playlist {
+        Some(audio::label_from_playlist(path)?)
+    } else {
+        None
+    };
+
+    let label = if let Some(mut label) = imported {
+        if let Some(title) = &args.title {
+            label.title = title.clone();
+        }
+        if let Some(artist) = &args.artist {
+            label.artist = artist.clone();
+        }
+        label.release_year = args.release_year.clone().or(label.release_year);
+        if let Some(cover) = &args.cover {
+            label.cover = cover.clone();
+            label.cover_data = None;
+        }
+        label
+    } else {
+        // clap makes sure these are all given when nothing is imported
+        Label {
+            title: args.title.clone().unwrap_or_default(),
+            artist: args.artist.clone().unwrap_or_default(),
+            release_year: args.release_year.clone(),
+            cover: args.cover.clone().unwrap_or_default(),
+            ..Default::default()
+        }
+    };
+
+    let label = Label { copies: args.copies, ..label };
+    let label_config = Config { labels: vec![label], ..Default::default() };
+    write_labels(&label_config, &args.render, &args.output)
+}
+
+// Make the labels listed in a layout, or one for every album in a music library
+fn batch(args: &BatchArgs) -> Result<(), Box<dyn Error>> {
+    let label_config = match (&args.layout, &args.scan) {
+        (Some(layout), _) => {
+            parse_config(&read_layout(layout)?, LayoutFormat::from_path(layout))?
+        },
+        (None, Some(root)) => Config { labels: audio::scan_library(root)?, ..Default::default() },
+        // clap makes sure one of them is given
+        (None, None) => unreachable!(),
+    };
+    write_labels(&label_config, &args.render, &args.output)
+}
+
+// The labels for a config, drawn and placed on sheets, with what's needed to finish them off
+struct DrawnLabels<'a> {
+    /// The details of each label, in the order they're on the sheets
+    label_infos: Vec<(&'a Label, LabelType)>,
+    sheets: Vec<Sheet>,
+    metrics: Metrics,
+    sheet_color: Rgb<u8>,
+    printer_profile: Option<PrinterProfile>,
+    alignment: Alignment,
+    mono: Option<Dither>,
+    cut_guides: Option<CutGuides>,
+}
+
+impl DrawnLabels<'_> {
+    // Anything done to a sheet or label once it's been drawn, before it's written out
+    fn finish(&self, mut raster: RgbImage) -> RgbImage {
+        if let Some(profile) = &self.printer_profile {
+            profile.apply(&mut raster);
+        }
+        match self.mono {
+            Some(dither) => dither.apply(&raster),
+            None => raster,
+        }
+    }
+
+    fn finish_sheet(&self, sheet: &Sheet) -> Result<RgbImage, Box<dyn Error>> {
+        let marks = self.marks(sheet);
+        let raster = rasterize_sheet(sheet, &self.metrics, self.sheet_color, &marks)?;
+        Ok(self.finish(self.alignment.apply(&raster, self.metrics.dpmm, self.sheet_color)))
+    }
+
+    // The cut guides for a sheet, if it has any
+    fn marks(&self, sheet: &Sheet) -> Vec<Rect> {
+        self.cut_guides.map_or(Vec::new(), |guides| guides::marks(sheet, guides, &self.metrics))
+    }
+}
+
+// Draw the labels for a config and lay them out on sheets
+fn draw_labels<'a>(
+    label_config: &'a Config,
+    args: &RenderArgs
+) -> Result<DrawnLabels<'a>, Box<dyn Error>> {
+    let theme = load_theme(args, label_config).map_err(error::config)?;
+    let context = render_context(args, label_config, &theme).map_err(error::config)?;
+
+    // Copies of a label are only drawn once, and each copy of a set is kept together
+    let bleed = args.bleed.or(label_config.bleed).map_or(0, |mm| context.metrics.px(mm));
+    let mut label_infos: Vec<(&Label, LabelType)> = Vec::new();
+    let mut labels = Vec::new();
+    for label_info in &label_config.labels {
+        let drawn = label_types(label_info, args.set).into_iter()
+            .map(|label_type| {
+                let label = render_label(label_info, label_type, &context).map_err(error::render)?;
+                let mut label = label.with_bleed(bleed);
+                let kind = format!("{:?}", label_type).to_lowercase();
+                let description = format!("the {} label for '{}'", kind, label_info.title);
+                for problem in validate::text_problems(&label, &description) {
+                    if context.strict {
+                        return Err(LabelError::Render(problem).into());
+                    }
+                    eprintln!("warning: {}", problem);
+                }
+                if args.debug_safe_area {
+                    debug::draw_safe_area(&mut label, &context.metrics);
+                }
+                if args.debug_layout {
+                    debug::log_layout(&label, &description);
+                    debug::draw_layout(&mut label, &context.metrics);
+                }
+                Ok((label_type, label))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        for _ in 0..label_info.copies.unwrap_or(1) {
+            for (label_type, label) in &drawn {
+                label_infos.push((label_info, *label_type));
+                labels.push(label.clone());
+            }
+        }
+    }
+    if labels.is_empty() {
+        return Err(LabelError::Config(
+            "there are no labels to draw, as none are listed or all have 0 copies".to_string()
+        ).into());
+    }
+    let (sheets, label_infos) = match args.sheet {
+        Some(sheet) => {
+            (template::place(labels, &sheet.template(), &context.metrics, args.pack)?, label_infos)
+        },
+        None => {
+            let (sheets, order) = place_labels(labels, &context.metrics, args.pack);
+            (sheets, order.into_iter().map(|i| label_infos[i]).collect())
+        },
+    };
+
+    let sheet_color = label_config.sheet_color.or(theme.sheet_color)
+        .map_or(SHEET_COLOR, |Color(color)| color);
+    let printer_profile = args.printer_profile.as_ref().or(label_config.printer_profile.as_ref())
+        .map(|name| profile::load(name))
+        .transpose()?;
+    let profile_alignment = printer_profile.as_ref().map(|profile| profile.alignment);
+    let alignment = label_config.printer.unwrap_or_default()
+        .or(&profile_alignment.unwrap_or_default());
+
+    Ok(DrawnLabels {
+        label_infos,
+        sheets,
+        metrics: context.metrics,
+        sheet_color,
+        printer_profile,
+        alignment,
+        mono: args.mono,
+        // Labels with bleed are cut at the crop marks
+        cut_guides: if bleed > 0 { Some(CutGuides::Ticks) } else { args.cut_guides },
+    })
+}
+
+// Draw the sheets for a layout and print them
+fn print(args: &PrintArgs) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "ble")]
+    if args.list_devices {
+        return ble::list_devices();
+    }
+
+    let layout_path = args.layout.as_deref().ok_or("give a layout to print with --layout")?;
+    let layout = read_layout(layout_path)?;
+    let label_config = parse_config(&layout, LayoutFormat::from_path(layout_path))?;
+    let drawn = draw_labels(&label_config, &args.render)?;
+
+    #[cfg(feature = "ble")]
+    if let Some(device) = &args.device {
+        let labels: Vec<RgbImage> = drawn.sheets.iter()
+            .flat_map(|sheet| &sheet.labels)
+            .map(|(_, _, label)| drawn.finish(overlay_text(&label.art, &label.text)))
+            .collect();
+        let dither = args.render.mono.unwrap_or_default();
+        return ble::print(device, &labels, drawn.metrics.dpmm, dither);
+    }
+
+    let sheets = drawn.sheets.iter()
+        .map(|sheet| drawn.finish_sheet(sheet))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let page_size = match &args.page_size {
+        Some(size) => parse_page_size(size)?,
+        None => drawn.metrics.printable_mm,
+    };
+    let title = format!("mdlabelgen - {}", layout_path);
+    let job = print::Job {
+        title: &title,
+        sheets: &sheets,
+        dpmm: drawn.metrics.dpmm,
+        media: args.media.as_deref(),
+        page_size,
+        scaling: args.scaling,
+    };
+    print::print(args.printer.as_deref(), &job)
+}
+
+// Save a test sheet for measuring a printer with. It's drawn at the size and resolution the labels
+// would be, so that it's printed the same way they are
+fn calibrate(args: &CalibrateArgs) -> Result<(), Box<dyn Error>> {
+    let mut metrics = match args.dpi {
+        Some(dpi) => Metrics::from_dpi(dpi),
+        None => Metrics::new(DESIRED_DPMM as f32),
+    };
+    if let Some(size) = &args.sheet_size {
+        metrics = metrics.with_sheet_size(parse_sheet_size(size)?);
+    }
+    let format = match args.format {
+        Some(format) => format,
+        None => OutputFormat::from_path(Path::new(&args.output))?,
+    };
+    if format == OutputFormat::Svg {
+        return Err("the test sheet can't be saved as SVG, save it as a PDF to print".into());
+    }
+
+    let overwrite = if args.force {
+        Overwrite::Replace
+    } else if args.no_clobber_rename {
+        Overwrite::Rename
+    } else {
+        Overwrite::Refuse
+    };
+    let path = output::claim(vec![PathBuf::from(&args.output)], overwrite)?.remove(0);
+    let sheet = calibrate::test_sheet(&metrics);
+    match format {
+        OutputFormat::Pdf => pdf::write_pdf(&[sheet], metrics.printable_mm, metrics.dpmm, &path),
+        format => output::save(&sheet, &path, format, &output::SaveOptions {
+            icc_profile: None,
+            cmyk: false,
+            quality: None,
+            dpmm: metrics.dpmm,
+            dither: Dither::default(),
+            escpos_width: metrics.printable_mm.0,
+            zpl_darkness: None,
+        }),
+    }
+}
+
+// A layout and the covers it uses, which are the files that change what its labels look like
+fn watched_files(layout: &str, label_config: &Config) -> Vec<PathBuf> {
+    std::iter::once(PathBuf::from(layout))
+        .chain(label_config.labels.iter()
+            .filter(|label_info| !label_info.cover.is_empty())
+            .map(|label_info| PathBuf::from(&label_info.cover)))
+        .collect()
+}
+
+// Write the labels for a layout, then write them again each time the layout or its covers are
+// saved. The first write goes by --force like any other, but after that the files it wrote are
+// replaced. Errors are reported without stopping, so that a broken layout can be fixed
+fn watch(args: &WatchArgs) -> Result<(), Box<dyn Error>> {
+    if args.layout == "-" {
+        return Err("watch needs a layout file to watch, not stdin".into());
+    }
+    if args.output.output.as_deref() == Some(output::STDOUT) {
+        return Err("watch can't write the labels to stdout".into());
+    }
+
+    let mut output_args = args.output.clone();
+    loop {
+        let mut files = vec![PathBuf::from(&args.layout)];
+        let written = read_layout(&args.layout)
+            .and_then(|layout| parse_config(&layout, LayoutFormat::from_path(&args.layout)))
+            .and_then(|label_config| {
+                files = watched_files(&args.layout, &label_config);
+                write_labels(&label_config, &args.render, &output_args)
+            });
+        match written {
+            Ok(()) => {
+                output_args.force = true;
+                output_args.no_clobber_rename = false;
+            },
+            Err(e) => eprintln!("error: {}", e),
+        }
+
+        eprintln!("Watching {} and its covers for changes, press Ctrl-C to stop", args.layout);
+        watch::Watched::new(files).wait();
+    }
+}
+
+// Draw the labels in a layout and show each sheet of them in the terminal, or in a window that
+// keeps up with changes to the layout
+fn preview(args: &PreviewArgs) -> Result<(), Box<dyn Error>> {
+    // The layout and covers are read afresh each time, and returned with the sheets so that
+    // they can be watched
+    let draw = || -> Result<(Vec<RgbImage>, Vec<PathBuf>), Box<dyn Error>> {
+        let layout = read_layout(&args.layout)?;
+        let label_config = parse_config(&layout, LayoutFormat::from_path(&args.layout))?;
+        let files = watched_files(&args.layout, &label_config);
+
+        let drawn = draw_labels(&label_config, &args.render)?;
+        let sheets = drawn.sheets.iter()
+            .map(|sheet| drawn.finish_sheet(sheet))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((sheets, files))
+    };
+
+    if args.watch_preview {
+        if args.layout == "-" {
+            return Err("--watch-preview needs a layout file to watch, not stdin".into());
+        }
+        return window::watch_preview(&format!("mdlabelgen - {}", args.layout), draw);
+    }
+    for sheet in draw()?.0 {
+        preview::show(&sheet, args.protocol, args.width)?;
+    }
+    Ok(())
+}
+
+// Draw the labels for a config, lay them out on sheets and save them
+fn write_labels(
+    label_config: &Config,
+    args: &RenderArgs,
+    output_args: &OutputArgs
+) -> Result<(), Box<dyn Error>> {
+    let output = output_args.output.clone();
+
+    let drawn = draw_labels(label_config, args)?;
+    let (label_infos, sheets, metrics) = (&drawn.label_infos, &drawn.sheets, &drawn.metrics);
+
+    // Files are named after the details of the first label on their sheet
+    let sheet_fields = sheets.iter()
+        .scan(0, |first, sheet| {
+            let (label_info, label_type) = label_infos[*first];
+            *first += sheet.labels.len();
+            Some(name_fields(label_info, label_type))
+        })
+        .collect::<Vec<_>>();
+
+    // Save the final files to disk. PDFs hold every sheet as a page of a single document
+    let format = match (output_args.format, &output) {
+        (Some(format), _) => format,
+        (None, Some(output)) if output == output::STDOUT => OutputFormat::Png,
+        (None, Some(output)) => OutputFormat::from_path(Path::new(output))?,
+        (None, None) => OutputFormat::Png,
+    };
+    if output_args.cmyk && format != OutputFormat::Tiff {
+        return Err("--cmyk can only be used with TIFF output".into());
+    }
+    if output_args.quality.is_some() && format != OutputFormat::Jpeg {
+        eprintln!("warning: --quality only applies to JPEG output, other formats are saved \
+            losslessly");
+    }
+    let options = output::SaveOptions {
+        icc_profile: output::icc_profile(
+            output_args.icc_profile.as_deref().or(label_config.icc_profile.as_deref()),
+            output_args.cmyk
+        )?,
+        cmyk: output_args.cmyk,
+        quality: output_args.quality,
+        dpmm: metrics.dpmm,
+        dither: drawn.mono.unwrap_or_default(),
+        escpos_width: output_args.escpos_width,
+        zpl_darkness: output_args.zpl_darkness,
+    };
+
+    // Work out where everything is going before writing any of it, so that nothing is written
+    // if any of it would replace an existing file
+    let pattern = &output_args.sheet_pattern;
+    let sheet_paths: Vec<PathBuf> = match (format, &output) {
+        (_, None) => Vec::new(),
+        (OutputFormat::Pdf, Some(output)) => {
+            vec![sheet_path(output, pattern, 0, 1, &sheet_fields[0])]
+        },
+        (_, Some(output)) => (0..sheets.len())
+            .map(|n| sheet_path(output, pattern, n, sheets.len(), &sheet_fields[n]))
+            .collect(),
+    };
+
+    // Each label can also be saved on its own, for printers that take one label at a time. They're
+    // saved as images in the sheet's format, or as PNGs when the sheets are PDFs or SVGs
+    let label_format = match format {
+        OutputFormat::Pdf | OutputFormat::Svg => OutputFormat::Png,
+        format => format,
+    };
+    let label_paths: Vec<PathBuf> = match &output_args.output_dir {
+        Some(dir) => label_infos.iter().enumerate()
+            .map(|(n, (label_info, label_type))| {
+                // `{n}` is the old name for `{num}`, which clap can't show in help text
+                let name = output_args.label_name
+                    .replace("{num}", &(n + 1).to_string())
+                    .replace("{n}", &(n + 1).to_string())
+                    .replace("{ext}", label_format.extension());
+                Path::new(dir).join(output::fill_template(&name,
+                    &name_fields(label_info, *label_type)))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    if sheet_paths.len() > 1 && output.as_deref() == Some(output::STDOUT) {
+        return Err(format!("there are {} sheets, which can only be written to stdout together as \
+            a PDF", sheet_paths.len()).into());
+    }
+
+    let overwrite = if output_args.force {
+        Overwrite::Replace
+    } else if output_args.no_clobber_rename {
+        Overwrite::Rename
+    } else {
+        Overwrite::Refuse
+    };
+    let sheet_count = sheet_paths.len();
+    let extra_paths = [&output_args.emit_layout, &output_args.manifest].into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect();
+    let paths = [sheet_paths, label_paths, extra_paths].concat();
+    // A dry run reports anything that's in the way rather than stopping at it
+    let mut sheet_paths = match output::claim(paths.clone(), overwrite) {
+        Err(e) if output_args.dry_run => {
+            eprintln!("warning: {}", e);
+            paths
+        },
+        claimed => claimed?,
+    };
+    let manifest_path = output_args.manifest.is_some().then(|| sheet_paths.pop()).flatten();
+    let layout_path = output_args.emit_layout.is_some().then(|| sheet_paths.pop()).flatten();
+    let label_paths = sheet_paths.split_off(sheet_count);
+
+    if output_args.dry_run {
+        let plural = |count: usize, noun: &str| {
+            format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+        };
+        println!("{} on {}", plural(label_infos.len(), "label"), plural(sheets.len(), "sheet"));
+        let extra_paths = layout_path.iter().chain(&manifest_path);
+        for path in sheet_paths.iter().chain(&label_paths).chain(extra_paths) {
+            println!("would write {}", path.display());
+        }
+        return Ok(());
+    }
+
+    match format {
+        _ if sheet_paths.is_empty() => {},
+        OutputFormat::Pdf => {
+            let page_size = match &output_args.page_size {
+                Some(size) => parse_page_size(size)?,
+                None => metrics.printable_mm,
+            };
+            let rasters = sheets.iter()
+                .map(|sheet| drawn.finish_sheet(sheet))
+                .collect::<Result<Vec<_>, _>>()?;
+            pdf::write_pdf(&rasters, page_size, metrics.dpmm, &sheet_paths[0])?;
+        },
+        OutputFormat::Svg => {
+            let aligned = drawn.alignment.offset_mm.is_some() || drawn.alignment.scale.is_some();
+            if drawn.mono.is_some() || drawn.printer_profile.is_some() || aligned {
+                return Err("--mono, printer profiles and printer alignment can't be used with SVG \
+                    output".into());
+            }
+            for (sheet, path) in sheets.iter().zip(&sheet_paths) {
+                svg::write_svg(sheet, metrics, drawn.sheet_color, &drawn.marks(sheet), path)?;
+            }
+        },
+        _ => {
+            for (sheet, path) in sheets.iter().zip(&sheet_paths) {
+                output::save(&drawn.finish_sheet(sheet)?, path, format, &options)?;
+            }
+        },
+    }
+
+    if let Some(dir) = &output_args.output_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("can't create output directory '{}': {}", dir, e))?;
+        let labels = sheets.iter().flat_map(|sheet| &sheet.labels);
+        for ((_, _, label), path) in labels.zip(&label_paths) {
+            let label = drawn.finish(overlay_text(&label.art, &label.text));
+            output::save(&label, path, label_format, &options)?;
+        }
+    }
+
+    if let Some(path) = &layout_path {
+        placement::write_placement(&drawn, path)?;
+    }
+    if let Some(path) = &manifest_path {
+        manifest::write_manifest(
+            &drawn, format, &sheet_paths, &label_paths, layout_path.as_ref(), path
+        )?;
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    match &args.command {
+        Command::Generate(generate_args) => generate(generate_args),
+        Command::Batch(batch_args) => batch(batch_args),
+        Command::Preview(preview_args) => preview(preview_args),
+        Command::Print(print_args) => print(print_args),
+        Command::Watch(watch_args) => watch(watch_args),
+        #[cfg(feature = "gui")]
+        Command::Gui(gui_args) => gui::run(gui_args),
+        Command::Fetch(fetch) => fetch::fetch(fetch),
+        Command::Schema => {
+            println!("{}", schema::layout_schema()?);
+            Ok(())
+        },
+        Command::Validate(validate) => validate::validate(validate),
+        Command::Init(init) => init::init(init),
+        Command::Calibrate(calibrate_args) => calibrate(calibrate_args),
+        Command::New(new_args) => wizard::new_labels(new_args),
+    }
+}
+
+fn main() -> ExitCode {
+    // Parse the main CLI options
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(error::exit_code(&*e))
+        },
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{output, DrawnLabels, OutputFormat};
+
+// Everything a run wrote, so that scripts making a lot of labels can keep track of them
+#[derive(Serialize)]
+struct Manifest {
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile {
+    path: String,
+    /// `sheet`, `label` or `layout`
+    kind: &'static str,
+    /// The numbers of the sheets in the file, or of the sheet a label is on, starting at 1
+    sheets: Vec<usize>,
+    labels: Vec<ManifestLabel>,
+    /// SHA-256 of the file's contents, in hex, or empty if it was written to stdout
+    sha256: String,
+}
+
+#[derive(Serialize, Clone)]
+struct ManifestLabel {
+    title: String,
+    artist: String,
+    #[serde(rename = "type")]
+    label_type: String,
+}
+
+// Hash a file that's just been written. Anything written to stdout can't be read back.
+fn file_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+    if output::is_stdout(path) {
+        return Ok(String::new());
+    }
+    let contents = fs::read(path)
+        .map_err(|e| format!("can't read back '{}': {}", path.display(), e))?;
+    Ok(Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Write out a manifest of the sheets, labels and layout that were saved, to a file or to stdout
+// when the path is `-`. Sheets are listed in order, and PDFs hold every sheet in the one file.
+pub fn write_manifest(
+    drawn: &DrawnLabels,
+    format: OutputFormat,
+    sheet_paths: &[PathBuf],
+    label_paths: &[PathBuf],
+    layout_path: Option<&PathBuf>,
+    path: &Path
+) -> Result<(), Box<dyn Error>> {
+    let labels: Vec<ManifestLabel> = drawn.label_infos.iter()
+        .map(|(label_info, label_type)| ManifestLabel {
+            title: label_info.title.clone(),
+            artist: label_info.artist.clone(),
+            label_type: format!("{:?}", label_type).to_lowercase(),
+        })
+        .collect();
+    let sheet_of: Vec<usize> = drawn.sheets.iter().enumerate()
+        .flat_map(|(n, sheet)| std::iter::repeat_n(n + 1, sheet.labels.len()))
+        .collect();
+    // The labels on any of the given sheets
+    let on_sheets = |sheets: &[usize]| -> Vec<ManifestLabel> {
+        labels.iter().zip(&sheet_of)
+            .filter(|(_, sheet)| sheets.contains(sheet))
+            .map(|(label, _)| label.clone())
+            .collect()
+    };
+    let file = |path: &PathBuf, kind, sheets, labels| -> Result<_, Box<dyn Error>> {
+        Ok(ManifestFile {
+            path: path.display().to_string(), kind, sheets, labels, sha256: file_hash(path)?
+        })
+    };
+
+    let mut files = Vec::new();
+    let all_sheets: Vec<usize> = (1..=drawn.sheets.len()).collect();
+    for (n, sheet_path) in sheet_paths.iter().enumerate() {
+        let sheets = match format {
+            OutputFormat::Pdf => all_sheets.clone(),
+            _ => vec![n + 1],
+        };
+        let labels = on_sheets(&sheets);
+        files.push(file(sheet_path, "sheet", sheets, labels)?);
+    }
+    for (n, label_path) in label_paths.iter().enumerate() {
+        files.push(file(label_path, "label", vec![sheet_of[n]], vec![labels[n].clone()])?);
+    }
+    if let Some(layout_path) = layout_path {
+        files.push(file(layout_path, "layout", all_sheets, labels.clone())?);
+    }
+
+    output::write_file(path, serde_json::to_string_pretty(&Manifest { files })?.as_bytes())
+}
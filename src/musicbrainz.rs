@@ -0,0 +1,141 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use serde_derive::Deserialize;
+
+use crate::audio;
+use crate::fetch::{self, FetchedLabel, FetchedTrack, USER_AGENT};
+
+const API: &str = "https://musicbrainz.org/ws/2";
+const COVER_ART_ARCHIVE: &str = "https://coverartarchive.org";
+
+// MusicBrainz asks clients to make no more than one request a second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct SearchResults {
+    releases: Vec<ReleaseSummary>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseSummary {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    title: String,
+    date: Option<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct Medium {
+    #[serde(default)]
+    tracks: Vec<ReleaseTrack>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseTrack {
+    title: String,
+    /// Length in milliseconds
+    length: Option<u64>,
+}
+
+fn get<T: serde::de::DeserializeOwned>(url: &str, query: &[(&str, &str)]) -> Result<T, Box<dyn Error>> {
+    let mut request = ureq::get(url).header("User-Agent", USER_AGENT);
+    for (key, value) in query {
+        request = request.query(*key, *value);
+    }
+    Ok(request.call()?.body_mut().read_json()?)
+}
+
+// Quote a value for a Lucene search query
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Find the best matching release for an artist and album title
+pub fn search(artist: &str, album: &str) -> Result<String, Box<dyn Error>> {
+    let query = format!("artist:{} AND release:{}", quote(artist), quote(album));
+    let results: SearchResults = get(
+        &format!("{}/release", API), &[("query", &query), ("limit", "1"), ("fmt", "json")]
+    )?;
+
+    let release = results.releases.into_iter().next()
+        .ok_or(format!("MusicBrainz has no release of '{}' by '{}'", album, artist))?;
+
+    thread::sleep(REQUEST_INTERVAL);
+    Ok(release.id)
+}
+
+// Look up a release on MusicBrainz by its ID. The front cover is downloaded from the Cover Art
+// Archive when there is one.
+pub fn fetch_release(id: &str) -> Result<FetchedLabel, Box<dyn Error>> {
+    let release: Release = get(
+        &format!("{}/release/{}", API, id), &[("inc", "recordings+artist-credits"), ("fmt", "json")]
+    )?;
+
+    let cover_url = format!("{}/release/{}/front-500", COVER_ART_ARCHIVE, id);
+    let cover = match fetch::download_cover(&cover_url, &format!("{}.jpg", id), &[]) {
+        Ok(Some(path)) => Some(path),
+        Ok(None) => {
+            eprintln!("warning: the Cover Art Archive has no front cover for this release");
+            None
+        },
+        Err(e) => {
+            eprintln!("warning: couldn't download the cover: {}", e);
+            None
+        },
+    };
+
+    Ok(FetchedLabel {
+        cover: cover.map(|path| path.to_string_lossy().to_string()),
+        artist: release.artist_credit.iter()
+            .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+            .collect(),
+        release_year: release.date.as_ref()
+            .and_then(|date| date.get(..4))
+            .map(str::to_string),
+        record_label: None,
+        catalog_number: None,
+        tracks: release.media.iter()
+            .flat_map(|medium| &medium.tracks)
+            .map(|track| FetchedTrack {
+                title: track.title.clone(),
+                duration: track.length.map(|ms| audio::format_duration(Duration::from_millis(ms))),
+            })
+            .collect(),
+        title: release.title,
+    })
+}
@@ -0,0 +1,385 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use imageproc::image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use imageproc::image::codecs::webp::WebPEncoder;
+use imageproc::image::{ExtendedColorType, ImageEncoder, RgbImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+use png::{PixelDimensions, Unit};
+use tiff::encoder::colortype::{ColorType, CMYK8, RGB8};
+use tiff::encoder::{Compression, Rational, TiffEncoder};
+use tiff::tags::{ResolutionUnit, Tag};
+
+use crate::dither::Dither;
+use crate::{brother, dymo, escpos, zpl};
+
+// The formats sheets can be saved in, and the extensions that are recognised as each
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Tiff,
+    Jpeg,
+    Webp,
+    Pdf,
+    Svg,
+    /// A print job for a Brother QL printer with 62 mm continuous tape
+    BrotherQl,
+    /// A print job for a DYMO LabelWriter with die-cut labels
+    Dymo,
+    /// A print job for a receipt or label printer that takes ESC/POS commands
+    EscPos,
+    /// A ZPL label for a Zebra printer
+    Zpl,
+}
+
+const EXTENSIONS: [(&str, OutputFormat); 10] = [
+    ("png", OutputFormat::Png),
+    ("tif", OutputFormat::Tiff),
+    ("tiff", OutputFormat::Tiff),
+    ("jpg", OutputFormat::Jpeg),
+    ("jpeg", OutputFormat::Jpeg),
+    ("webp", OutputFormat::Webp),
+    ("pdf", OutputFormat::Pdf),
+    ("svg", OutputFormat::Svg),
+    ("prn", OutputFormat::BrotherQl),
+    ("zpl", OutputFormat::Zpl),
+];
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Tiff => "tif",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Svg => "svg",
+            OutputFormat::BrotherQl => "prn",
+            // There's no extension for LabelWriter jobs, so it's not one that's recognised
+            OutputFormat::Dymo => "bin",
+            OutputFormat::EscPos => "bin",
+            OutputFormat::Zpl => "zpl",
+        }
+    }
+
+    // Work out the format from an output path's extension, which isn't case sensitive
+    pub fn from_path(path: &Path) -> Result<OutputFormat, Box<dyn Error>> {
+        let names = || EXTENSIONS.iter().map(|(ext, _)| *ext).collect::<Vec<_>>().join(", ");
+        let Some(extension) = path.extension() else {
+            return Err(format!("can't tell what format to save '{}' in without an extension, \
+                give it one of {} or use --format", path.display(), names()).into());
+        };
+        let extension = extension.to_string_lossy().to_lowercase();
+        EXTENSIONS.iter()
+            .find(|(ext, _)| *ext == extension)
+            .map(|(_, format)| *format)
+            .ok_or_else(|| format!("can't save sheets as '.{}' files, the supported formats are \
+                {}. Use --format to save in one of them regardless of the extension",
+                extension, names()).into())
+    }
+}
+
+// The output path that means the file is written to stdout instead, so it can be piped
+pub const STDOUT: &str = "-";
+
+// Quality of JPEGs when it isn't given, high enough that text stays crisp
+const JPEG_QUALITY: u8 = 90;
+
+// How sheets are written to disk
+pub struct SaveOptions {
+    /// ICC profile to embed, if there's one for the colorspace the sheet is written in
+    pub icc_profile: Option<Vec<u8>>,
+    /// Write TIFFs in CMYK, for print shops that won't take RGB
+    pub cmyk: bool,
+    /// JPEG quality, from 1 to 100
+    pub quality: Option<u8>,
+    pub dpmm: f32,
+    /// How shades are turned to black and white for label printers that only print black
+    pub dither: Dither,
+    /// Width in mm that ESC/POS printers print across
+    pub escpos_width: f32,
+    /// How dark Zebra printers print, from 0 to 30, if it's to be changed
+    pub zpl_darkness: Option<u8>,
+}
+
+// The ICC profile to embed in saved sheets. Sheets are drawn in sRGB, which is what's embedded
+// unless a profile is given by path, eg. one made for the printer's paper. CMYK sheets need a CMYK
+// profile to be converted with, and are converted without one if none is given.
+pub fn icc_profile(path: Option<&str>, cmyk: bool) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let Some(path) = path else {
+        return Ok((!cmyk).then(|| ColorProfile::new_srgb().encode()).transpose()?);
+    };
+    let profile = fs::read(path)
+        .map_err(|e| format!("can't read ICC profile '{}': {}", path, e))?;
+    let color_space = ColorProfile::new_from_slice(&profile)
+        .map_err(|e| format!("'{}' isn't a usable ICC profile: {}", path, e))?
+        .color_space;
+
+    match (color_space, cmyk) {
+        (DataColorSpace::Rgb, false) | (DataColorSpace::Cmyk, true) => Ok(Some(profile)),
+        (DataColorSpace::Cmyk, false) => {
+            Err(format!("'{}' is a CMYK profile, it can only be used with --cmyk", path).into())
+        },
+        _ => Err(format!("'{}' isn't {} profile", path, if cmyk { "a CMYK" } else { "an RGB" })
+            .into()),
+    }
+}
+
+// Convert a sheet to CMYK, through the printer's profile when there is one. Otherwise the black
+// is taken from how dark each pixel is and the rest made up with cyan, magenta and yellow.
+fn to_cmyk(sheet: &RgbImage, icc_profile: Option<&[u8]>) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(profile) = icc_profile {
+        let profile = ColorProfile::new_from_slice(profile)?;
+        let transform = ColorProfile::new_srgb().create_transform_8bit(
+            Layout::Rgb, &profile, Layout::Rgba, TransformOptions::default()
+        )?;
+        let mut cmyk = vec![0; sheet.width() as usize * sheet.height() as usize * 4];
+        transform.transform(sheet.as_raw(), &mut cmyk)?;
+        return Ok(cmyk);
+    }
+
+    Ok(sheet.pixels().flat_map(|pixel| {
+        let [r, g, b] = pixel.0.map(|c| c as f32 / 255.0);
+        let k = 1.0 - r.max(g).max(b);
+        let ink = |c: f32| if k < 1.0 { (1.0 - c - k) / (1.0 - k) } else { 0.0 };
+        [ink(r), ink(g), ink(b), k].map(|c| (c * 255.0).round() as u8)
+    }).collect())
+}
+
+// PNGs also record how many pixels there are to a meter, so that they're printed at the size of
+// the sheet rather than at whatever resolution the printing app assumes
+fn encode_png(
+    file: impl Write,
+    sheet: &RgbImage,
+    options: &SaveOptions
+) -> Result<(), Box<dyn Error>> {
+    let mut info = png::Info::with_size(sheet.width(), sheet.height());
+    info.color_type = png::ColorType::Rgb;
+    info.bit_depth = png::BitDepth::Eight;
+    info.icc_profile = options.icc_profile.as_deref().map(Cow::Borrowed);
+    let pixels_per_meter = (options.dpmm * 1000.0).round() as u32;
+    info.pixel_dims = Some(PixelDimensions {
+        xppu: pixels_per_meter, yppu: pixels_per_meter, unit: Unit::Meter,
+    });
+
+    let mut writer = png::Encoder::with_info(file, info)?.write_header()?;
+    writer.write_image_data(sheet.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
+
+// TIFFs are LZW compressed, which every print shop's software can open, and carry their
+// resolution in dots per centimeter
+fn encode_tiff<C: ColorType<Inner = u8>>(
+    file: impl Write + Seek,
+    (width, height): (u32, u32),
+    data: &[u8],
+    options: &SaveOptions
+) -> Result<(), Box<dyn Error>> {
+    let mut encoder = TiffEncoder::new(file)?.with_compression(Compression::Lzw);
+    let mut image = encoder.new_image::<C>(width, height)?;
+    image.resolution(ResolutionUnit::Centimeter,
+        Rational { n: (options.dpmm * 1000.0).round() as u32, d: 100 });
+    if let Some(profile) = &options.icc_profile {
+        image.encoder().write_tag(Tag::IccProfile, profile.as_slice())?;
+    }
+    image.write_data(data)?;
+    Ok(())
+}
+
+fn encode(
+    mut encoder: impl ImageEncoder,
+    sheet: &RgbImage,
+    options: &SaveOptions
+) -> Result<(), Box<dyn Error>> {
+    if let Some(profile) = &options.icc_profile {
+        encoder.set_icc_profile(profile.clone())?;
+    }
+    encoder.write_image(sheet.as_raw(), sheet.width(), sheet.height(), ExtendedColorType::Rgb8)?;
+    Ok(())
+}
+
+// Write a sheet to disk as an image. The color profile is embedded in every format, so print
+// software doesn't have to guess at the colorspace. WebPs are always lossless, JPEGs are as lossy
+// as the quality they're saved at. Label printer jobs are written in place of images, since
+// they're all the printer needs. PDFs and SVGs aren't single images, and are written elsewhere.
+pub fn save(
+    sheet: &RgbImage,
+    path: &Path,
+    format: OutputFormat,
+    options: &SaveOptions
+) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    let file = Cursor::new(&mut data);
+    match format {
+        OutputFormat::Png => encode_png(file, sheet, options),
+        OutputFormat::Jpeg => {
+            let mut encoder =
+                JpegEncoder::new_with_quality(file, options.quality.unwrap_or(JPEG_QUALITY));
+            encoder.set_pixel_density(PixelDensity::dpi((options.dpmm * 25.4).round() as u16));
+            encode(encoder, sheet, options)
+        },
+        OutputFormat::Webp => encode(WebPEncoder::new_lossless(file), sheet, options),
+        OutputFormat::Tiff if options.cmyk => {
+            let cmyk = to_cmyk(sheet, options.icc_profile.as_deref())?;
+            encode_tiff::<CMYK8>(file, sheet.dimensions(), &cmyk, options)
+        },
+        OutputFormat::Tiff => {
+            encode_tiff::<RGB8>(file, sheet.dimensions(), sheet.as_raw(), options)
+        },
+        OutputFormat::BrotherQl => {
+            data = brother::raster(sheet, options.dpmm, options.dither)?;
+            Ok(())
+        },
+        OutputFormat::Dymo => {
+            data = dymo::raster(sheet, options.dpmm, options.dither)?;
+            Ok(())
+        },
+        OutputFormat::EscPos => {
+            data = escpos::raster(sheet, options.dpmm, options.escpos_width, options.dither)?;
+            Ok(())
+        },
+        OutputFormat::Zpl => {
+            data = zpl::label(sheet, options.dpmm, options.zpl_darkness, options.dither)?;
+            Ok(())
+        },
+        OutputFormat::Pdf | OutputFormat::Svg => Err("PDFs and SVGs aren't saved as images".into()),
+    }?;
+    write_file(path, &data)
+}
+
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new(STDOUT)
+}
+
+// Write a finished file to disk, or to stdout when its path is `-`
+pub fn write_file(path: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    if is_stdout(path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    fs::write(path, data).map_err(|e| format!("can't write '{}': {}", path.display(), e).into())
+}
+
+// Make text from a label safe to use in a file name, replacing the characters that aren't allowed
+// in names on some systems, or that would make it a path, with underscores
+pub fn sanitize(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    name.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string()
+}
+
+// Fill in the placeholders of a file name template with the details of a label, eg.
+// `{artist}-{title}-{num}.png`
+pub fn fill_template(template: &str, fields: &[(&str, String)]) -> String {
+    fields.iter().fold(template.to_string(), |name, (field, value)| {
+        name.replace(&format!("{{{}}}", field), &sanitize(value))
+    })
+}
+
+// What to do when a file that's about to be written is already there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overwrite {
+    Refuse,
+    Replace,
+    Rename,
+}
+
+// Add a number to a file name, eg. `sheet.png` becomes `sheet-2.png`
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}-{}.{}", stem, number, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}-{}", stem, number)),
+    }
+}
+
+// Check the paths files are about to be written to, before any of them are. Unless existing files
+// are to be replaced, either stop if any of them are already there, or find names for them that
+// aren't taken yet. Paths that come up more than once, like labels named the same by a pattern,
+// would be written over by the later files, so they're numbered when renaming and stop the run
+// otherwise.
+pub fn claim(paths: Vec<PathBuf>, overwrite: Overwrite) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if overwrite != Overwrite::Rename {
+        let mut seen = HashSet::new();
+        let mut repeated: Vec<String> = Vec::new();
+        for path in paths.iter().filter(|path| !is_stdout(path)) {
+            let name = format!("'{}'", path.display());
+            if !seen.insert(path) && !repeated.contains(&name) {
+                repeated.push(name);
+            }
+        }
+        if !repeated.is_empty() {
+            return Err(format!("{} would be written more than once, give names that tell the \
+                files apart, eg. with `{{num}}`, or use --no-clobber-rename to number them",
+                repeated.join(", ")).into());
+        }
+    }
+
+    match overwrite {
+        Overwrite::Replace => Ok(paths),
+        Overwrite::Refuse => {
+            let existing: Vec<String> = paths.iter()
+                .filter(|path| !is_stdout(path) && path.exists())
+                .map(|path| format!("'{}'", path.display()))
+                .collect();
+            if existing.is_empty() {
+                return Ok(paths);
+            }
+            let (verb, them) = match existing.len() {
+                1 => ("exists", "it"),
+                _ => ("exist", "them"),
+            };
+            Err(format!("{} already {}, use --force to replace {} or --no-clobber-rename to save \
+                alongside {}", existing.join(", "), verb, them, them).into())
+        },
+        Overwrite::Rename => {
+            let mut claimed = HashSet::new();
+            Ok(paths.into_iter()
+                .map(|path| {
+                    let free = |path: &PathBuf| !path.exists() && !claimed.contains(path);
+                    if is_stdout(&path) || free(&path) {
+                        claimed.insert(path.clone());
+                        return path;
+                    }
+                    let renamed = (2..)
+                        .map(|number| numbered(&path, number))
+                        .find(free)
+                        .unwrap_or(path);
+                    claimed.insert(renamed.clone());
+                    renamed
+                })
+                .collect())
+        },
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use imageproc::image::{imageops, DynamicImage, Rgb};
+
+// The cover is shrunk to this size before it's analysed, which is plenty to find its main colors
+const SAMPLE_SIZE: u32 = 64;
+// How many groups of similar colors the cover's pixels are split into
+const SWATCHES: usize = 8;
+// How far apart, as a distance in RGB space, the secondary color has to be from the dominant one
+// to be told apart from it at a glance
+const MIN_CONTRAST: f32 = 96.0;
+
+// The main colors of a cover, for labels that take their colors from their artwork
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    /// The color covering most of the cover
+    pub dominant: Rgb<u8>,
+    /// The color covering most of the rest of the cover, that stands apart from the dominant one
+    pub secondary: Rgb<u8>,
+}
+
+// A group of pixels with similar colors
+struct Swatch {
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Swatch {
+    // The channel whose values are most spread out, and how far they're spread
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3).map(|c| {
+            let values = self.pixels.iter().map(|pixel| pixel[c]);
+            (c, values.clone().max().unwrap_or(0) - values.min().unwrap_or(0))
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+    }
+
+    fn average(&self) -> Rgb<u8> {
+        let count = self.pixels.len().max(1) as u32;
+        let mut total = [0u32; 3];
+        for pixel in &self.pixels {
+            for c in 0..3 {
+                total[c] += pixel[c] as u32;
+            }
+        }
+        Rgb(total.map(|sum| (sum / count) as u8))
+    }
+}
+
+fn distance(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
+    (0..3).map(|c| (a[c] as f32 - b[c] as f32).powi(2)).sum::<f32>().sqrt()
+}
+
+// Find the main colors of a cover with median cut. The pixels start in a single swatch, which is
+// repeatedly split in half along the channel its colors vary most in. The largest swatch once
+// they've been split up is the dominant color.
+pub fn extract(cover: &DynamicImage) -> Palette {
+    let sample = cover.resize_exact(SAMPLE_SIZE, SAMPLE_SIZE, imageops::FilterType::Triangle)
+        .to_rgb8();
+    let mut swatches = vec![Swatch { pixels: sample.pixels().map(|pixel| pixel.0).collect() }];
+
+    while swatches.len() < SWATCHES {
+        let Some((i, channel)) = swatches.iter().enumerate()
+            .filter(|(_, swatch)| swatch.pixels.len() > 1)
+            .map(|(i, swatch)| (i, swatch.widest_channel()))
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|(_, (_, range))| *range)
+            .map(|(i, (channel, _))| (i, channel))
+        else {
+            break;
+        };
+
+        let swatch = &mut swatches[i];
+        swatch.pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = swatch.pixels.split_off(swatch.pixels.len() / 2);
+        swatches.push(Swatch { pixels: upper });
+    }
+
+    swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.pixels.len()));
+    let colors: Vec<Rgb<u8>> = swatches.iter().map(Swatch::average).collect();
+    let dominant = colors[0];
+    let secondary = colors.iter()
+        .find(|color| distance(**color, dominant) >= MIN_CONTRAST)
+        .or(colors.get(1))
+        .copied()
+        .unwrap_or(dominant);
+    Palette { dominant, secondary }
+}
@@ -0,0 +1,94 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::error::Error;
+use std::path::Path;
+
+use imageproc::image::RgbImage;
+use miniz_oxide::deflate::{compress_to_vec_zlib, CompressionLevel};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref};
+
+use crate::output;
+
+// PDF user space units are points, 1/72 of an inch
+const PT_PER_MM: f32 = 72.0 / 25.4;
+
+// Write every sheet as a page of a single PDF, as `pdf` does, and save it to `path`
+pub fn write_pdf(
+    sheets: &[RgbImage],
+    page_size: (f32, f32),
+    dpmm: f32,
+    path: &Path
+) -> Result<(), Box<dyn Error>> {
+    output::write_file(path, &pdf(sheets, page_size, dpmm))
+}
+
+// Make a PDF with every sheet as a page. Each page is `page_size` mm and has its sheet centred on
+// it, scaled so that `dpmm` pixels take up exactly one millimetre on paper.
+pub fn pdf(sheets: &[RgbImage], page_size: (f32, f32), dpmm: f32) -> Vec<u8> {
+    let mut pdf = Pdf::new();
+    let mut next_id = Ref::new(1);
+    let mut alloc = || next_id.bump();
+
+    let catalog_id = alloc();
+    let page_tree_id = alloc();
+    let page_ids: Vec<Ref> = sheets.iter().map(|_| alloc()).collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+
+    let page_rect = Rect::new(0.0, 0.0, page_size.0 * PT_PER_MM, page_size.1 * PT_PER_MM);
+    let image_name = Name(b"Im1");
+
+    for (sheet, page_id) in sheets.iter().zip(page_ids) {
+        let image_id = alloc();
+        let content_id = alloc();
+
+        let mut page = pdf.page(page_id);
+        page.media_box(page_rect);
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources().x_objects().pair(image_name, image_id);
+        page.finish();
+
+        let encoded = compress_to_vec_zlib(sheet.as_raw(), CompressionLevel::DefaultLevel as u8);
+        let mut image = pdf.image_xobject(image_id, &encoded);
+        image.filter(Filter::FlateDecode);
+        image.width(sheet.width() as i32);
+        image.height(sheet.height() as i32);
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.finish();
+
+        // Images are drawn into a 1x1 unit square, so scale it up to the physical size of the
+        // sheet and centre it on the page
+        let w = sheet.width() as f32 / dpmm * PT_PER_MM;
+        let h = sheet.height() as f32 / dpmm * PT_PER_MM;
+        let x = (page_rect.x2 - w) / 2.0;
+        let y = (page_rect.y2 - h) / 2.0;
+
+        let mut content = Content::new();
+        content.save_state();
+        content.transform([w, 0.0, 0.0, h, x, y]);
+        content.x_object(image_name);
+        content.restore_state();
+        pdf.stream(content_id, &content.finish());
+    }
+
+    pdf.finish()
+}
@@ -0,0 +1,131 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde_derive::Serialize;
+
+use crate::layout::Rect;
+use crate::{output, text, DrawnLabels};
+
+// Where everything was placed on each sheet, for tools that cut out or show the labels without
+// working the layout out again. Positions are from the top left of the sheet, before any printer
+// alignment is applied.
+#[derive(Serialize)]
+struct Placement {
+    dpi: f32,
+    sheet: Bounds,
+    sheets: Vec<PlacedSheet>,
+}
+
+#[derive(Serialize)]
+struct PlacedSheet {
+    labels: Vec<PlacedLabel>,
+}
+
+#[derive(Serialize)]
+struct PlacedLabel {
+    title: String,
+    artist: String,
+    #[serde(rename = "type")]
+    label_type: String,
+    /// Where the label is cut
+    trim: Bounds,
+    /// The whole of the label's artwork, including any bleed past the trim
+    art: Bounds,
+    elements: BTreeMap<String, Bounds>,
+    lines: Vec<PlacedLine>,
+}
+
+#[derive(Serialize)]
+struct PlacedLine {
+    text: String,
+    bounds: Bounds,
+}
+
+// A rectangle in both mm and pixels, as x, y, width and height
+#[derive(Serialize)]
+struct Bounds {
+    mm: [f32; 4],
+    px: [i32; 4],
+}
+
+impl Bounds {
+    fn new(rect: Rect, dpmm: f32) -> Bounds {
+        let mm = |px: i32| (px as f32 / dpmm * 100.0).round() / 100.0;
+        Bounds {
+            mm: [mm(rect.x), mm(rect.y), mm(rect.width), mm(rect.height)],
+            px: [rect.x, rect.y, rect.width, rect.height],
+        }
+    }
+}
+
+// Write out where each label, element of a label and line of text was placed on the sheets, to a
+// file or to stdout when the path is `-`
+pub fn write_placement(drawn: &DrawnLabels, path: &Path) -> Result<(), Box<dyn Error>> {
+    let dpmm = drawn.metrics.dpmm;
+    let mut label_infos = drawn.label_infos.iter();
+    let sheets = drawn.sheets.iter()
+        .map(|sheet| PlacedSheet {
+            labels: sheet.labels.iter().zip(label_infos.by_ref())
+                .map(|((x, y, label), (label_info, label_type))| {
+                    let (x, y) = (*x as i32, *y as i32);
+                    let on_sheet = |rect: &Rect| {
+                        Bounds::new(Rect { x: x + rect.x, y: y + rect.y, ..*rect }, dpmm)
+                    };
+                    let (width, height) = (label.art.width() as i32, label.art.height() as i32);
+                    let bleed = label.bleed;
+                    PlacedLabel {
+                        title: label_info.title.clone(),
+                        artist: label_info.artist.clone(),
+                        label_type: format!("{:?}", label_type).to_lowercase(),
+                        trim: on_sheet(&Rect {
+                            x: bleed, y: bleed, width: width - 2 * bleed, height: height - 2 * bleed
+                        }),
+                        art: on_sheet(&Rect { x: 0, y: 0, width, height }),
+                        elements: label.elements.iter()
+                            .map(|(name, rect)| (name.clone(), on_sheet(rect)))
+                            .collect(),
+                        lines: label.text.iter()
+                            .map(|line| PlacedLine {
+                                text: line.text.clone(),
+                                bounds: on_sheet(&text::line_bounds(line)),
+                            })
+                            .collect(),
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let metrics = &drawn.metrics;
+    let placement = Placement {
+        dpi: (dpmm * crate::MM_PER_INCH * 100.0).round() / 100.0,
+        sheet: Bounds::new(Rect {
+            x: 0,
+            y: 0,
+            width: metrics.printable_width_px as i32,
+            height: metrics.printable_height_px as i32,
+        }, dpmm),
+        sheets,
+    };
+    output::write_file(path, serde_json::to_string_pretty(&placement)?.as_bytes())
+}
@@ -0,0 +1,220 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::env;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{self, Cursor, Write};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use imageproc::image::imageops::{self, FilterType};
+use imageproc::image::{ImageFormat, RgbImage};
+
+use crate::dither::BAYER;
+
+// Terminals don't say how big their character cells are, but they're usually about twice as
+// tall as they are wide, and around ten pixels across
+const CELL_ASPECT: f32 = 2.0;
+const CELL_WIDTH: u32 = 10;
+
+// Kitty takes images in pieces of at most this many bytes of base64
+const KITTY_CHUNK: usize = 4096;
+
+// How an image is shown in the terminal. Kitty, iTerm2 and sixel graphics show it at close to
+// full resolution in terminals that support them, and blocks draw it with colored half-height
+// characters in any terminal that has 24-bit color.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Auto,
+    Kitty,
+    Iterm,
+    Sixel,
+    Blocks,
+}
+
+impl Protocol {
+    // Work out what the terminal supports from the variables it sets. These mostly make it
+    // through SSH, apart from TERM_PROGRAM, so blocks are used when nothing better is found.
+    fn detect() -> Protocol {
+        let var = |name| env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+
+        if !var("KITTY_WINDOW_ID").is_empty() || term == "xterm-kitty" || program == "ghostty" {
+            Protocol::Kitty
+        } else if matches!(program.as_str(), "iTerm.app" | "WezTerm")
+            || var("LC_TERMINAL") == "iTerm2" {
+            Protocol::Iterm
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Protocol::Sixel
+        } else {
+            Protocol::Blocks
+        }
+    }
+}
+
+// How many columns wide to show an image so that it fits in the terminal, leaving a couple of
+// rows free for the prompt
+fn fit_columns(image: &RgbImage) -> u32 {
+    let (columns, rows) = terminal_size::terminal_size()
+        .map_or((80, 24), |(width, height)| (width.0 as u32, height.0 as u32));
+    let aspect = image.width() as f32 / image.height() as f32;
+    let columns_for_rows = (rows.saturating_sub(2) as f32 * CELL_ASPECT * aspect) as u32;
+    columns.min(columns_for_rows).max(1)
+}
+
+// Scale an image down to `width` pixels across, keeping its shape
+fn shrink(image: &RgbImage, width: u32) -> RgbImage {
+    let width = width.min(image.width()).max(1);
+    let height = (image.height() as f32 * width as f32 / image.width() as f32)
+        .round().max(1.0) as u32;
+    imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+fn png(image: &RgbImage) -> Result<String, Box<dyn Error>> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok(STANDARD.encode(data))
+}
+
+// Kitty's graphics protocol, with the image sent as a PNG in pieces
+fn kitty(image: &RgbImage, columns: u32) -> Result<String, Box<dyn Error>> {
+    let data = png(image)?;
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let control = match i {
+            0 => format!("a=T,f=100,c={},m={}", columns, more),
+            _ => format!("m={}", more),
+        };
+        write!(out, "\x1b_G{};{}\x1b\\", control, std::str::from_utf8(chunk)?)?;
+    }
+    Ok(out)
+}
+
+// iTerm2's inline images, which WezTerm understands too
+fn iterm(image: &RgbImage, columns: u32) -> Result<String, Box<dyn Error>> {
+    let data = png(image)?;
+    Ok(format!(
+        "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07",
+        data.len() * 3 / 4, columns, data
+    ))
+}
+
+// Sixel graphics, with the colors dithered to a 6x6x6 cube. The image is sent in bands six
+// pixels high, with a row of characters for each color in the band whose bits say which of the
+// six pixels in each column are that color.
+fn sixel(image: &RgbImage) -> String {
+    let indexes: Vec<u32> = image.enumerate_pixels()
+        .map(|(x, y, pixel)| {
+            let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0;
+            let level = |value: u8| (value as f32 / 51.0 + threshold).floor().min(5.0) as u32;
+            level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+        })
+        .collect();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        let (r, g, b) = (index / 36, index / 6 % 6, index % 6);
+        let _ = write!(out, "#{};2;{};{};{}", index, r * 20, g * 20, b * 20);
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let mut colors: Vec<u32> = rows.clone()
+            .flat_map(|y| indexes[y * width..(y + 1) * width].iter().copied())
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for color in colors {
+            let _ = write!(out, "#{}", color);
+            let column = |x: usize| rows.clone()
+                .filter(|y| indexes[y * width + x] == color)
+                .fold(0u8, |bits, y| bits | 1 << (y - top));
+
+            // Runs of the same character are written once with a count
+            let mut x = 0;
+            while x < width {
+                let bits = column(x);
+                let run = (x..width).take_while(|&next| column(next) == bits).count();
+                let char = (63 + bits) as char;
+                if run > 3 {
+                    let _ = write!(out, "!{}{}", run, char);
+                } else {
+                    out.extend(std::iter::repeat_n(char, run));
+                }
+                x += run;
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// Upper half blocks, with the top pixel of each character cell as its foreground and the bottom
+// one as its background
+fn blocks(image: &RgbImage) -> String {
+    let mut out = String::new();
+    for top in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let upper = image.get_pixel(x, top);
+            let _ = write!(out, "\x1b[38;2;{};{};{}m", upper[0], upper[1], upper[2]);
+            if top + 1 < image.height() {
+                let lower = image.get_pixel(x, top + 1);
+                let _ = write!(out, "\x1b[48;2;{};{};{}m", lower[0], lower[1], lower[2]);
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+// Show an image in the terminal, `columns` characters wide or as big as will fit
+pub fn show(
+    image: &RgbImage,
+    protocol: Protocol,
+    columns: Option<u32>
+) -> Result<(), Box<dyn Error>> {
+    let protocol = match protocol {
+        Protocol::Auto => Protocol::detect(),
+        protocol => protocol,
+    };
+    let columns = columns.unwrap_or_else(|| fit_columns(image));
+
+    let out = match protocol {
+        Protocol::Kitty => kitty(&shrink(image, columns * CELL_WIDTH), columns)? + "\n",
+        Protocol::Iterm => iterm(&shrink(image, columns * CELL_WIDTH), columns)? + "\n",
+        Protocol::Sixel => sixel(&shrink(image, columns * CELL_WIDTH)) + "\n",
+        // Each character is two pixels high, which makes them about square
+        _ => blocks(&shrink(image, columns)),
+    };
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(out.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
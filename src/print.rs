@@ -0,0 +1,211 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::env;
+use std::error::Error;
+#[cfg(not(all(windows, feature = "windows-print")))]
+use std::io::{self, Write};
+#[cfg(not(all(windows, feature = "windows-print")))]
+use std::process::{Command, Stdio};
+
+use imageproc::image::RgbImage;
+
+use crate::pdf;
+
+// IPP attribute group and value tags, from RFC 8010
+const OPERATION_ATTRIBUTES: u8 = 0x01;
+const JOB_ATTRIBUTES: u8 = 0x02;
+const END_OF_ATTRIBUTES: u8 = 0x03;
+const NAME: u8 = 0x42;
+const KEYWORD: u8 = 0x44;
+const URI: u8 = 0x45;
+const CHARSET: u8 = 0x47;
+const NATURAL_LANGUAGE: u8 = 0x48;
+const MIME_MEDIA_TYPE: u8 = 0x49;
+
+// The IPP Print-Job operation
+const PRINT_JOB: u16 = 0x0002;
+
+// How the printer should size the sheets to the paper it's printing on
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Scaling {
+    /// Print at actual size, so the labels come out the size they're meant to be
+    None,
+    /// Shrink or grow each sheet until it fits on the paper
+    Fit,
+    /// Grow each sheet until it covers the paper, cutting off anything that's over
+    Fill,
+}
+
+impl Scaling {
+    // The `print-scaling` keyword that asks for this, which CUPS takes as an option too
+    fn keyword(self) -> &'static str {
+        match self {
+            Scaling::None => "none",
+            Scaling::Fit => "fit",
+            Scaling::Fill => "fill",
+        }
+    }
+}
+
+// Sheets to print, and how to print them
+pub struct Job<'a> {
+    pub title: &'a str,
+    pub sheets: &'a [RgbImage],
+    // How many pixels of the sheets there are to a millimetre, to print them at their real size
+    pub dpmm: f32,
+    pub media: Option<&'a str>,
+    // The size in mm of the page each sheet is centred on, which is the paper asked for when no
+    // media is given
+    pub page_size: (f32, f32),
+    pub scaling: Scaling,
+}
+
+// Print a job, sending it straight to a printer given by its `ipp://` or `ipps://` address, or
+// to a printer given by name, or to the default printer when there's no name. Named printers are
+// printed to through CUPS, or the print spooler on Windows when built with `windows-print`
+pub fn print(printer: Option<&str>, job: &Job) -> Result<(), Box<dyn Error>> {
+    match printer {
+        Some(uri) if uri.starts_with("ipp://") || uri.starts_with("ipps://") => ipp(uri, job),
+        #[cfg(all(windows, feature = "windows-print"))]
+        printer => crate::winprint::print(printer, job),
+        #[cfg(not(all(windows, feature = "windows-print")))]
+        printer => lp(printer, job),
+    }
+}
+
+// Hand a job to CUPS as a PDF with `lp`, which reads the document from stdin
+#[cfg(not(all(windows, feature = "windows-print")))]
+fn lp(printer: Option<&str>, job: &Job) -> Result<(), Box<dyn Error>> {
+    let (width, height) = job.page_size;
+    let media = job.media.map_or_else(|| format!("Custom.{}x{}mm", width, height), str::to_string);
+
+    let mut command = Command::new("lp");
+    if let Some(printer) = printer {
+        command.args(["-d", printer]);
+    }
+    command.args(["-t", job.title])
+        .args(["-o", &format!("media={}", media)])
+        .args(["-o", &format!("print-scaling={}", job.scaling.keyword())])
+        .stdin(Stdio::piped());
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => "can't find lp to print with, is CUPS installed? Printers can \
+            also be given by their ipp:// address".to_string(),
+        _ => format!("can't run lp: {}", e),
+    })?;
+    let document = pdf::pdf(job.sheets, job.page_size, job.dpmm);
+    child.stdin.take().ok_or("can't write to lp")?.write_all(&document)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("lp couldn't print the sheets ({})", status).into());
+    }
+    Ok(())
+}
+
+fn attribute(request: &mut Vec<u8>, tag: u8, name: &str, value: &str) {
+    request.push(tag);
+    request.extend((name.len() as u16).to_be_bytes());
+    request.extend(name.as_bytes());
+    request.extend((value.len() as u16).to_be_bytes());
+    request.extend(value.as_bytes());
+}
+
+// The value of an attribute in an IPP response, if it's there
+fn response_attribute<'a>(response: &'a [u8], wanted: &str) -> Option<&'a [u8]> {
+    let mut rest = response.get(8..)?;
+    while let Some((&tag, after)) = rest.split_first() {
+        // Group tags stand on their own, without a name or value
+        if tag <= 0x0f {
+            rest = after;
+            continue;
+        }
+        let length = |bytes: &[u8]| Some(u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]));
+        let name_length = length(after)? as usize;
+        let name = after.get(2..2 + name_length)?;
+        let value_length = length(&after[2 + name_length..])? as usize;
+        let value_start = 4 + name_length;
+        let value = after.get(value_start..value_start + value_length)?;
+        if name == wanted.as_bytes() {
+            return Some(value);
+        }
+        rest = &after[value_start + value_length..];
+    }
+    None
+}
+
+// Send a job straight to a printer with an IPP Print-Job request, over HTTP to port 631 unless
+// the address gives another
+fn ipp(uri: &str, job: &Job) -> Result<(), Box<dyn Error>> {
+    let (scheme, address) = uri.split_once("://").ok_or("invalid printer address")?;
+    let (host, path) = address.split_once('/').unwrap_or((address, ""));
+    let port = if host.rsplit(']').next().unwrap_or(host).contains(':') { "" } else { ":631" };
+    let url = match scheme {
+        "ipps" => format!("https://{}{}/{}", host, port, path),
+        _ => format!("http://{}{}/{}", host, port, path),
+    };
+
+    let (width, height) = job.page_size;
+    let media = job.media.map_or_else(
+        || format!("custom_mdlabelgen_{}x{}mm", width, height),
+        str::to_string
+    );
+    let user = env::var("USER").or(env::var("USERNAME")).unwrap_or("mdlabelgen".to_string());
+
+    let mut request = vec![2, 0];
+    request.extend(PRINT_JOB.to_be_bytes());
+    request.extend(1u32.to_be_bytes());
+    request.push(OPERATION_ATTRIBUTES);
+    attribute(&mut request, CHARSET, "attributes-charset", "utf-8");
+    attribute(&mut request, NATURAL_LANGUAGE, "attributes-natural-language", "en");
+    attribute(&mut request, URI, "printer-uri", uri);
+    attribute(&mut request, NAME, "requesting-user-name", &user);
+    attribute(&mut request, NAME, "job-name", job.title);
+    attribute(&mut request, MIME_MEDIA_TYPE, "document-format", "application/pdf");
+    request.push(JOB_ATTRIBUTES);
+    attribute(&mut request, KEYWORD, "media", &media);
+    attribute(&mut request, KEYWORD, "print-scaling", job.scaling.keyword());
+    request.push(END_OF_ATTRIBUTES);
+    request.extend(pdf::pdf(job.sheets, job.page_size, job.dpmm));
+
+    let response = ureq::post(&url)
+        .header("Content-Type", "application/ipp")
+        .send(&request[..])
+        .map_err(|e| format!("can't send the sheets to {}: {}", uri, e))?
+        .body_mut()
+        .read_to_vec()?;
+
+    // Status codes below 0x0100 are successes, some with attributes the printer ignored
+    let status = match response.get(2..4) {
+        Some(status) => u16::from_be_bytes([status[0], status[1]]),
+        None => return Err(format!("{} didn't send back a valid IPP response", uri).into()),
+    };
+    if status >= 0x0100 {
+        let message = response_attribute(&response, "status-message")
+            .map_or(format!("status 0x{:04x}", status), |message| {
+                String::from_utf8_lossy(message).to_string()
+            });
+        return Err(format!("{} refused the sheets: {}", uri, message).into());
+    }
+    // job-id is a 4 byte integer rather than text
+    if let Some(&[a, b, c, d]) = response_attribute(&response, "job-id") {
+        println!("Sent to {} as job {}", uri, u32::from_be_bytes([a, b, c, d]));
+    }
+    Ok(())
+}
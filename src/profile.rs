@@ -0,0 +1,157 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use imageproc::image::{self, Rgb, RgbImage};
+use schemars::JsonSchema;
+use serde_derive::Deserialize;
+
+use crate::adjust::Adjustments;
+
+// Profiles that are compiled into the binary for the printers labels are usually made for
+const BUILT_IN: [(&str, &str); 1] = [
+    ("zink", include_str!("../res/profiles/zink.toml")),
+];
+
+// A printer profile corrects a finished sheet for how a printer reproduces color, so that what
+// comes out matches what's on screen, eg.
+//
+//   gamma = [0.88, 0.8, 0.88]
+//   gain = [1.0, 1.0, 0.97]
+//   saturation = 1.15
+//
+// `gamma` bends the tone curve of red, green and blue, values under 1 lighten the midtones and
+// over 1 darken them, without changing black or white. `gain` then scales each channel, which
+// takes out a color cast everywhere including the white of the sheet. Any of the
+// brightness, contrast and saturation adjustments made to covers can be added after that.
+// Profiles can also correct where the printer puts things, with the settings of `Alignment`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct PrinterProfile {
+    pub gamma: Option<[f32; 3]>,
+    pub gain: Option<[f32; 3]>,
+    #[serde(flatten)]
+    pub adjust: Adjustments,
+    #[serde(flatten)]
+    pub alignment: Alignment,
+}
+
+// Corrections for printers that don't print things where they should be, measured from a sheet
+// printed with `calibrate`, eg.
+//
+//   offset_mm = [-1.5, 0]
+//   scale = [0.99, 1.0]
+//
+// moves everything 1.5mm left, for a printer that prints it that far right, and shrinks it to 99%
+// across, for one that prints 1% too wide. Sheets are scaled from their top left corner before
+// they're moved.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+pub struct Alignment {
+    /// How far to move everything on the sheet, across and down, in mm
+    pub offset_mm: Option<[f32; 2]>,
+    /// How much to scale everything on the sheet by, across and down
+    pub scale: Option<[f32; 2]>,
+}
+
+impl Alignment {
+    // Fill in anything unset from the more general settings
+    pub fn or(&self, fallback: &Alignment) -> Alignment {
+        Alignment {
+            offset_mm: self.offset_mm.or(fallback.offset_mm),
+            scale: self.scale.or(fallback.scale),
+        }
+    }
+
+    // Scale and move a sheet, filling anything uncovered with `background`
+    pub fn apply(&self, sheet: &RgbImage, dpmm: f32, background: Rgb<u8>) -> RgbImage {
+        let [scale_x, scale_y] = self.scale.unwrap_or([1.0, 1.0]);
+        let [offset_x, offset_y] = self.offset_mm.unwrap_or([0.0, 0.0]);
+        if [scale_x, scale_y] == [1.0, 1.0] && [offset_x, offset_y] == [0.0, 0.0] {
+            return sheet.clone();
+        }
+
+        let (width, height) = sheet.dimensions();
+        let scaled = image::imageops::resize(sheet,
+            ((width as f32 * scale_x).round() as u32).max(1),
+            ((height as f32 * scale_y).round() as u32).max(1),
+            image::imageops::FilterType::CatmullRom
+        );
+        let mut aligned = RgbImage::from_pixel(width, height, background);
+        image::imageops::overlay(&mut aligned, &scaled,
+            (offset_x * dpmm).round() as i64, (offset_y * dpmm).round() as i64
+        );
+        aligned
+    }
+}
+
+impl PrinterProfile {
+    // The curve for each channel, worked out once for every value it can have
+    fn curves(&self) -> [[u8; 256]; 3] {
+        let gamma = self.gamma.unwrap_or([1.0; 3]);
+        let gain = self.gain.unwrap_or([1.0; 3]);
+        let mut curves = [[0; 256]; 3];
+        for (channel, curve) in curves.iter_mut().enumerate() {
+            for (value, out) in curve.iter_mut().enumerate() {
+                let level = (value as f32 / 255.0).powf(gamma[channel]) * gain[channel];
+                *out = (level * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        curves
+    }
+
+    pub fn apply(&self, sheet: &mut RgbImage) {
+        let curves = self.curves();
+        for pixel in sheet.pixels_mut() {
+            let Rgb([r, g, b]) = *pixel;
+            let curved = Rgb([curves[0][r as usize], curves[1][g as usize], curves[2][b as usize]]);
+            *pixel = self.adjust.adjust_pixel(curved);
+        }
+    }
+}
+
+// Profiles that aren't given as a path are looked for by name in the `profiles` directory of the
+// user's config directory, eg. `~/.config/mdlabelgen/profiles/my-zink.toml`
+fn profile_path(name: &str) -> Option<PathBuf> {
+    if Path::new(name).is_file() {
+        return Some(PathBuf::from(name));
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("mdlabelgen").join("profiles").join(format!("{}.toml", name)))
+        .filter(|path| path.is_file())
+}
+
+// Load a printer profile given either as a path to a profile file or the name of an installed or
+// built in one
+pub fn load(name: &str) -> Result<PrinterProfile, Box<dyn Error>> {
+    let Some(path) = profile_path(name) else {
+        let (_, profile) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+                format!("there's no printer profile called '{}', the built in profiles are {}",
+                    name, names.join(", "))
+            })?;
+        return Ok(toml::from_str(profile)?);
+    };
+    let profile = fs::read_to_string(&path)
+        .map_err(|e| format!("can't read printer profile '{}': {}", path.display(), e))?;
+    Ok(toml::from_str(&profile)
+        .map_err(|e| format!("printer profile '{}': {}", path.display(), e))?)
+}
@@ -0,0 +1,43 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::Config;
+
+// A JSON Schema describing layout files, for editors to check and complete them with. It works
+// for TOML and YAML layouts as well as JSON ones, since they all describe the same settings.
+pub fn layout_schema() -> Result<String, Box<dyn Error>> {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config))?;
+
+    // `[defaults]` isn't part of the config itself, it's merged into each label as the layout is
+    // read. It takes anything a label does, and since any of that can come from the defaults
+    // there's nothing a label has to give itself.
+    if let Some(label) = schema["$defs"]["Label"].as_object_mut() {
+        label.remove("required");
+    }
+    schema["properties"]["defaults"] = json!({
+        "$ref": "#/$defs/Label",
+        "description": "Settings copied into every label that doesn't give its own",
+    });
+
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
@@ -0,0 +1,126 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::error::Error;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::Path;
+
+use ab_glyph::{Font, ScaleFont};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use imageproc::image::{ImageFormat, Rgb, RgbImage};
+
+use crate::guides::GUIDE_COLOR;
+use crate::layout::Rect;
+use crate::output;
+use crate::{Metrics, Sheet, TextLine, TextOrientation};
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn hex(color: Rgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn png_data_uri(image: &RgbImage) -> Result<String, Box<dyn Error>> {
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    Ok(format!("data:image/png;base64,{}", BASE64.encode(png.into_inner())))
+}
+
+// SVG positions text on its baseline and sizes it by the em square, whereas the rasterizer
+// works from the top of the line and scales by the font height
+fn write_text(svg: &mut String, x: i64, y: i64, line: &TextLine) -> Result<(), Box<dyn Error>> {
+    let font = &line.font.font;
+    let scaled = font.as_scaled(line.scale);
+    let font_size = scaled.h_scale_factor() * font.units_per_em().unwrap_or(1.0);
+    let (left, top) = (x as f32 + line.x as f32, y as f32 + line.y as f32);
+
+    // Rotated lines are set horizontally then turned about the top right of their column, and
+    // vertical lines are left to the renderer to set down the middle of theirs
+    let placement = match line.orientation {
+        TextOrientation::Horizontal => format!(r#"x="{}" y="{}""#, left, top + scaled.ascent()),
+        TextOrientation::Rotated => format!(
+            r#"x="0" y="{}" transform="translate({} {}) rotate(90)""#,
+            scaled.ascent(), left + line.scale.y, top
+        ),
+        TextOrientation::Vertical => format!(
+            r#"x="{}" y="{}" writing-mode="vertical-rl""#, left + line.scale.y / 2.0, top
+        ),
+    };
+
+    // Text refers to fonts by family name, so editors can substitute an installed copy. Fallback
+    // fonts are listed after the main font for the renderer to pick missing characters from
+    let families: Vec<String> = std::iter::once(&line.font)
+        .chain(&line.font.fallbacks)
+        .map(|font| format!("'{}'", font.family))
+        .collect();
+
+    writeln!(svg,
+        r#"    <text {} font-family="{}" font-weight="{}" font-style="{}" font-size="{}" fill="{}" xml:space="preserve">{}</text>"#,
+        placement, escape(&families.join(", ")), line.font.weight,
+        if line.font.italic { "italic" } else { "normal" }, font_size, hex(line.color),
+        escape(&line.text)
+    )?;
+    Ok(())
+}
+
+// Write a sheet as an SVG document. The artwork of each label is embedded as a PNG and the text
+// is written as editable text elements over the top of it. Guide marks go under the labels
+pub fn write_svg(
+    sheet: &Sheet,
+    metrics: &Metrics,
+    background: Rgb<u8>,
+    marks: &[Rect],
+    path: &Path
+) -> Result<(), Box<dyn Error>> {
+    let mut svg = String::new();
+
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}mm" height="{}mm" viewBox="0 0 {} {}">"#,
+        metrics.printable_mm.0, metrics.printable_mm.1, metrics.printable_width_px,
+        metrics.printable_height_px
+    )?;
+    writeln!(svg, r#"  <rect width="100%" height="100%" fill="{}"/>"#, hex(background))?;
+    for mark in marks {
+        writeln!(svg, r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            mark.x, mark.y, mark.width, mark.height, hex(GUIDE_COLOR))?;
+    }
+
+    for (x, y, label) in &sheet.labels {
+        writeln!(svg, "  <g>")?;
+        writeln!(svg,
+            r#"    <image x="{}" y="{}" width="{}" height="{}" href="{}"/>"#,
+            x, y, label.art.width(), label.art.height(), png_data_uri(&label.art)?
+        )?;
+        for line in &label.text {
+            write_text(&mut svg, *x, *y, line)?;
+        }
+        writeln!(svg, "  </g>")?;
+    }
+
+    writeln!(svg, "</svg>")?;
+    output::write_file(path, svg.as_bytes())?;
+    Ok(())
+}
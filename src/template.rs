@@ -0,0 +1,124 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use crate::{Metrics, Pack, RenderedLabel, Sheet};
+
+// Sheets of die-cut labels that labels can be laid out on, one to each label on the sheet
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SheetTemplate {
+    /// Twelve 2 inch square labels on US Letter
+    #[value(name = "avery-22806")]
+    Avery22806,
+    /// Ten 4 x 2 inch labels on US Letter
+    #[value(name = "avery-5163")]
+    Avery5163,
+    /// Ten 99.1 x 57 mm labels on A4, the layout of most 10 up A4 sticker sheets
+    #[value(name = "avery-l7173", alias = "a4-10")]
+    AveryL7173,
+}
+
+// Where the labels are on a sheet, in mm. They're in a grid, with the first in the top left
+// corner and the rest `pitch` apart across and down the page
+pub struct Template {
+    pub page: (f32, f32),
+    pub label: (f32, f32),
+    pub columns: u32,
+    pub rows: u32,
+    pub first: (f32, f32),
+    pub pitch: (f32, f32),
+}
+
+impl SheetTemplate {
+    pub fn template(self) -> Template {
+        match self {
+            SheetTemplate::Avery22806 => Template {
+                page: (215.9, 279.4),
+                label: (50.8, 50.8),
+                columns: 3,
+                rows: 4,
+                first: (15.88, 15.88),
+                pitch: (66.68, 65.62),
+            },
+            SheetTemplate::Avery5163 => Template {
+                page: (215.9, 279.4),
+                label: (101.6, 50.8),
+                columns: 2,
+                rows: 5,
+                first: (3.97, 12.7),
+                pitch: (104.78, 50.8),
+            },
+            SheetTemplate::AveryL7173 => Template {
+                page: (210.0, 297.0),
+                label: (99.1, 57.0),
+                columns: 2,
+                rows: 5,
+                first: (4.65, 6.0),
+                pitch: (101.6, 57.0),
+            },
+        }
+    }
+}
+
+// Lay labels out on template sheets, one to each die-cut label and centred on it, across each row
+// and then down the page, using as many sheets as it takes. Labels that can be rotated are turned
+// sideways when they only fit on the die-cut labels that way. Any bleed runs off the die-cut label
+// onto the backing, so it isn't counted when checking that a label fits.
+pub fn place(
+    labels: Vec<RenderedLabel>,
+    template: &Template,
+    metrics: &Metrics,
+    pack: Pack
+) -> Result<Vec<Sheet>, Box<dyn Error>> {
+    let px = |mm: f32| (mm * metrics.dpmm).round() as i64;
+    let (width, height) = (px(template.label.0), px(template.label.1));
+    let slots = (template.columns * template.rows) as usize;
+
+    let mut sheets: Vec<Sheet> = Vec::new();
+    for (n, mut label) in labels.into_iter().enumerate() {
+        let bleed = label.bleed as i64;
+        let (mut label_width, mut label_height) =
+            (label.art.width() as i64 - 2 * bleed, label.art.height() as i64 - 2 * bleed);
+        let upright = label_width <= width && label_height <= height;
+        if !upright && pack == Pack::Rotate && label_height <= width && label_width <= height {
+            label = label.turned();
+            (label_width, label_height) = (label_height, label_width);
+        }
+        if label_width > width || label_height > height {
+            return Err(format!("a {:.1}x{:.1} mm label doesn't fit on the {}x{} mm labels of the \
+                sheet", label_width as f32 / metrics.dpmm, label_height as f32 / metrics.dpmm,
+                template.label.0, template.label.1).into());
+        }
+
+        let slot = n % slots;
+        if slot == 0 {
+            sheets.push(Sheet { labels: Vec::new() });
+        }
+        let column = (slot % template.columns as usize) as f32;
+        let row = (slot / template.columns as usize) as f32;
+        let x = px(template.first.0 + column * template.pitch.0) + (width - label_width) / 2;
+        let y = px(template.first.1 + row * template.pitch.1) + (height - label_height) / 2;
+        let (x, y) = (x - bleed, y - bleed);
+        if let Some(sheet) = sheets.last_mut() {
+            sheet.labels.push((x, y, label));
+        }
+    }
+    Ok(sheets)
+}
@@ -0,0 +1,278 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+use ab_glyph::{point, Font, FontArc, Glyph, GlyphId, PxScale, ScaleFont};
+use imageproc::image::RgbImage;
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+
+use crate::fonts::LabelFont;
+use crate::layout::Rect;
+use crate::{TextLine, TextOrientation};
+
+// A glyph positioned on a line of text, and the font it comes from. Sideways glyphs are laid out
+// as horizontal text, then turned a quarter turn clockwise to start the given distance down the
+// line.
+struct PlacedGlyph<'a> {
+    font: &'a FontArc,
+    glyph: Glyph,
+    sideways: Option<f32>,
+}
+
+// Split text into runs of characters that are drawn from the same font in the fallback chain
+fn font_runs<'a, 'b>(text: &'b str, font: &'a LabelFont) -> Vec<(&'b str, &'a LabelFont)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut current: Option<&LabelFont> = None;
+
+    for (i, c) in text.char_indices() {
+        let char_font = font.for_char(c);
+        if let Some(run_font) = current {
+            if !std::ptr::eq(run_font, char_font) {
+                runs.push((&text[start..i], run_font));
+                start = i;
+            }
+        }
+        current = Some(char_font);
+    }
+
+    if let Some(run_font) = current {
+        runs.push((&text[start..], run_font));
+    }
+    runs
+}
+
+// Shape a run of text in a single font and direction. The shaper works in font units, so its
+// positions are scaled to pixels the same way ab_glyph scales the outlines. Each glyph is passed
+// to `place` with its id, offset and advance in pixels.
+fn shape_run(
+    text: &str,
+    font: &LabelFont,
+    direction: Direction,
+    scale: PxScale,
+    mut place: impl FnMut(GlyphId, (f32, f32), (f32, f32))
+) {
+    let Some(face) = Face::from_slice(&font.data, font.index) else { return };
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(direction);
+    let shaped = rustybuzz::shape(&face, &[], buffer);
+
+    let scaled = font.font.as_scaled(scale);
+    let (h_scale, v_scale) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+
+    for (info, pos) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+        place(
+            GlyphId(info.glyph_id as u16),
+            (pos.x_offset as f32 * h_scale, -pos.y_offset as f32 * v_scale),
+            (pos.x_advance as f32 * h_scale, -pos.y_advance as f32 * v_scale),
+        );
+    }
+}
+
+// Lay out a line of horizontal text, taking every character from the first font in the fallback
+// chain that has it. Mixed direction text is split into runs and reordered for display, then
+// each run is shaped so that joining scripts like Arabic connect up and right-to-left text reads
+// correctly. Glyphs are positioned relative to the top left of the line, with every font sharing
+// the baseline of the primary font. Returns the glyphs and the line width.
+fn layout_horizontal<'a>(
+    text: &str,
+    font: &'a LabelFont,
+    scale: PxScale
+) -> (Vec<PlacedGlyph<'a>>, f32) {
+    let baseline = font.font.as_scaled(scale).ascent();
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+
+    let bidi = BidiInfo::new(text, None);
+    for paragraph in &bidi.paragraphs {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+
+        for run in runs {
+            let (direction, rtl) = if levels[run.start].is_rtl() {
+                (Direction::RightToLeft, true)
+            } else {
+                (Direction::LeftToRight, false)
+            };
+
+            // The shaper lays out a right-to-left run from its visual left edge, so the font
+            // runs within it need to go in reverse order as well
+            let mut font_runs = font_runs(&text[run], font);
+            if rtl {
+                font_runs.reverse();
+            }
+
+            for (run_text, run_font) in font_runs {
+                shape_run(run_text, run_font, direction, scale, |id, offset, advance| {
+                    glyphs.push(PlacedGlyph {
+                        font: &run_font.font,
+                        glyph: id.with_scale_and_position(
+                            scale, point(x + offset.0, baseline + offset.1)
+                        ),
+                        sideways: None,
+                    });
+                    x += advance.0;
+                });
+            }
+        }
+    }
+
+    (glyphs, x)
+}
+
+// Whether a character stays upright in vertical text. This roughly follows the Unicode vertical
+// orientation property: CJK scripts, fullwidth forms and pictographs are upright, and everything
+// else is set sideways.
+fn is_upright(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF | 0x2E80..=0x2FFF | 0x3000..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xA960..=0xA97F | 0xAC00..=0xD7FF | 0xF900..=0xFAFF | 0xFE10..=0xFE1F
+        | 0xFE30..=0xFE4F | 0xFF00..=0xFFEF | 0x1F000..=0x1FAFF | 0x20000..=0x3FFFF
+    )
+}
+
+// Lay out a column of vertical text (tategaki) running down from the top left of the column.
+// Upright characters are stacked down the centre of the column, and runs of other characters,
+// like Latin words, are set as horizontal text turned on their side. Returns the glyphs and the
+// length of the column.
+fn layout_vertical<'a>(
+    text: &str,
+    font: &'a LabelFont,
+    scale: PxScale
+) -> (Vec<PlacedGlyph<'a>>, f32) {
+    let centre = scale.y / 2.0;
+    let mut glyphs = Vec::new();
+    let mut y = 0.0;
+
+    let mut segments: Vec<(bool, &str)> = Vec::new();
+    let mut start = 0;
+    let mut current: Option<bool> = None;
+    for (i, c) in text.char_indices() {
+        if let Some(upright) = current {
+            if upright != is_upright(c) {
+                segments.push((upright, &text[start..i]));
+                start = i;
+            }
+        }
+        current = Some(is_upright(c));
+    }
+    if let Some(upright) = current {
+        segments.push((upright, &text[start..]));
+    }
+
+    for (upright, segment) in segments {
+        if !upright {
+            let (sideways, length) = layout_horizontal(segment, font, scale);
+            glyphs.extend(sideways.into_iter().map(|placed| PlacedGlyph {
+                sideways: Some(y),
+                ..placed
+            }));
+            y += length;
+            continue;
+        }
+
+        for (run_text, run_font) in font_runs(segment, font) {
+            shape_run(run_text, run_font, Direction::TopToBottom, scale, |id, offset, advance| {
+                glyphs.push(PlacedGlyph {
+                    font: &run_font.font,
+                    glyph: id.with_scale_and_position(
+                        scale, point(centre + offset.0, y + offset.1)
+                    ),
+                    sideways: None,
+                });
+                y += advance.1;
+            });
+        }
+    }
+
+    (glyphs, y)
+}
+
+fn layout<'a>(
+    text: &str,
+    font: &'a LabelFont,
+    scale: PxScale,
+    orientation: TextOrientation
+) -> (Vec<PlacedGlyph<'a>>, f32) {
+    match orientation {
+        TextOrientation::Horizontal => layout_horizontal(text, font, scale),
+        TextOrientation::Rotated => {
+            let (glyphs, length) = layout_horizontal(text, font, scale);
+            let glyphs = glyphs.into_iter()
+                .map(|placed| PlacedGlyph { sideways: Some(0.0), ..placed })
+                .collect();
+            (glyphs, length)
+        },
+        TextOrientation::Vertical => layout_vertical(text, font, scale),
+    }
+}
+
+// The length of a line of text in pixels, along the direction it runs in
+pub fn text_length(
+    text: &str,
+    font: &LabelFont,
+    scale: PxScale,
+    orientation: TextOrientation
+) -> f32 {
+    layout(text, font, scale, orientation).1
+}
+
+// The area of the label a line of text covers
+pub fn line_bounds(line: &TextLine) -> Rect {
+    let length = text_length(&line.text, &line.font, line.scale, line.orientation).round() as i32;
+    let thickness = line.scale.y.round() as i32;
+    match line.orientation {
+        TextOrientation::Horizontal => Rect { x: line.x, y: line.y, width: length, height: thickness },
+        _ => Rect { x: line.x, y: line.y, width: thickness, height: length },
+    }
+}
+
+// Draw a line of text onto a label in its color. Rotated and vertical lines run down the image from the top
+// left of the line, in a column as wide as a horizontal line is tall.
+pub fn draw_line(image: &mut RgbImage, line: &TextLine) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let (x, y, color) = (line.x, line.y, line.color);
+    let thickness = line.scale.y.round() as i32;
+
+    for placed in layout(&line.text, &line.font, line.scale, line.orientation).0 {
+        let Some(outline) = placed.font.outline_glyph(placed.glyph) else { continue };
+        let bounds = outline.px_bounds();
+
+        outline.draw(|gx, gy, coverage| {
+            let gx = bounds.min.x.round() as i32 + gx as i32;
+            let gy = bounds.min.y.round() as i32 + gy as i32;
+
+            // Turning a glyph clockwise takes the top of the line to the right of the column
+            let (px, py) = match placed.sideways {
+                Some(start) => (x + thickness - 1 - gy, y + start.round() as i32 + gx),
+                None => (x + gx, y + gy),
+            };
+            if px < 0 || py < 0 || px >= width || py >= height {
+                return;
+            }
+
+            let coverage = coverage.clamp(0.0, 1.0);
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for c in 0..3 {
+                pixel[c] = (pixel[c] as f32 * (1.0 - coverage) + color[c] as f32 * coverage)
+                    .round() as u8;
+            }
+        });
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Deserialize;
+
+use crate::color::{Color, CoverColor, TextColor};
+use crate::fill::Gradient;
+use crate::layout::Placement;
+use crate::{
+    AccentConfig, Align, CoverLayout, LabelGeometry, LogoConfig, ScrimConfig, TextOverflow
+};
+
+// Themes that are compiled into the binary, so there's always something to choose from
+const BUILT_IN: [(&str, &str); 4] = [
+    ("classic", include_str!("../res/themes/classic.toml")),
+    ("minimal", include_str!("../res/themes/minimal.toml")),
+    ("retro-90s", include_str!("../res/themes/retro-90s.toml")),
+    ("photo-full-bleed", include_str!("../res/themes/photo-full-bleed.toml")),
+];
+
+// A theme is a named set of styles for labels, kept in a TOML file so that the look of a label
+// can be changed without touching the code, eg.
+//
+//   background = "#f4ecd8"
+//   text_color = "#1a1a1a"
+//   font = "Noto Serif Bold"
+//   text_size = 2.2
+//   logo = { position = "top-right", size = 5 }
+//
+// Anything a theme leaves out keeps its default. Sizes are in mm.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Theme {
+    pub cover_layout: Option<CoverLayout>,
+    #[serde(alias = "background_color")]
+    pub background: Option<CoverColor>,
+    pub gradient: Option<Gradient>,
+    pub text_color: Option<TextColor>,
+    pub sheet_color: Option<Color>,
+    /// Fonts, by path or installed name, for all of the text and for each line of the face
+    pub font: Option<String>,
+    pub title_font: Option<String>,
+    pub artist_font: Option<String>,
+    pub year_font: Option<String>,
+    #[serde(default)]
+    pub fallback_fonts: Vec<String>,
+    pub align: Option<Align>,
+    pub text_overflow: Option<TextOverflow>,
+    pub text_size: Option<f32>,
+    pub min_text_size: Option<f32>,
+    pub spine_text_size: Option<f32>,
+    pub track_text_size: Option<f32>,
+    pub footer_text_size: Option<f32>,
+    /// Space between the edges of a label and what's on it
+    pub padding: Option<f32>,
+    /// Space between labels on a sheet
+    pub margin: Option<f32>,
+    pub logo: Option<LogoConfig>,
+    pub accent: Option<AccentConfig>,
+    pub scrim: Option<ScrimConfig>,
+    /// Size of the labels and where the cover and text go on them
+    pub label: Option<LabelGeometry>,
+    /// Where each element of a face label goes, eg. `title = { anchor = "top-left" }`
+    #[serde(default)]
+    pub layout: HashMap<String, Placement>,
+}
+
+// Themes that aren't given as a path are looked for by name in the `themes` directory of the
+// user's config directory, eg. `~/.config/mdlabelgen/themes/dark.toml`
+fn theme_path(name: &str) -> Option<PathBuf> {
+    if Path::new(name).is_file() {
+        return Some(PathBuf::from(name));
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("mdlabelgen").join("themes").join(format!("{}.toml", name)))
+        .filter(|path| path.is_file())
+}
+
+// Load a theme given either as a path to a theme file or the name of an installed one. Installed
+// themes take precedence over the built in ones, so they can be replaced.
+pub fn load(name: &str) -> Result<Theme, Box<dyn Error>> {
+    let Some(path) = theme_path(name) else {
+        let (_, theme) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+                format!("there's no theme called '{}', the built in themes are {}",
+                    name, names.join(", "))
+            })?;
+        return Ok(toml::from_str(theme)?);
+    };
+    let theme = fs::read_to_string(&path)
+        .map_err(|e| format!("can't read theme '{}': {}", path.display(), e))?;
+    Ok(toml::from_str(&theme).map_err(|e| format!("theme '{}': {}", path.display(), e))?)
+}
@@ -0,0 +1,68 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+
+use imageproc::image::{imageops, Rgb, RgbImage};
+
+use crate::dither::Dither;
+
+// Scale a sheet to a label printer's resolution and dither it to black and white. It's turned on
+// its side if that's the only way it fits across the printer's `width` dots, and it's an error if
+// it doesn't fit either way round on the `media` the printer takes.
+pub fn dots(
+    sheet: &RgbImage,
+    dpmm: f32,
+    printer_dpmm: f32,
+    width: u32,
+    media: &str,
+    dither: Dither
+) -> Result<RgbImage, Box<dyn Error>> {
+    let scale = printer_dpmm / dpmm;
+    let size = ((sheet.width() as f32 * scale).round() as u32,
+        (sheet.height() as f32 * scale).round() as u32);
+    let mut dots = if size == sheet.dimensions() {
+        sheet.clone()
+    } else {
+        imageops::resize(sheet, size.0, size.1, imageops::FilterType::Lanczos3)
+    };
+    if dots.width() > width {
+        if dots.height() > width {
+            return Err(format!("a {:.0}x{:.0} mm sheet is too big to fit on {} either way round",
+                size.0 as f32 / printer_dpmm, size.1 as f32 / printer_dpmm, media).into());
+        }
+        dots = imageops::rotate90(&dots);
+    }
+    Ok(dither.apply(&dots))
+}
+
+// Whether a dot of a dithered sheet is printed
+pub fn is_black(pixel: &Rgb<u8>) -> bool {
+    *pixel == Rgb([0, 0, 0])
+}
+
+// Pack a line of dots into bytes, eight to a byte with the first dot in the highest bit, the way
+// label printers take them
+pub fn pack(line: &[bool]) -> Vec<u8> {
+    line.chunks(8)
+        .map(|dots| dots.iter().enumerate()
+            .filter(|(_, black)| **black)
+            .fold(0, |byte, (n, _)| byte | 0x80 >> n))
+        .collect()
+}
@@ -0,0 +1,257 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use crate::layout::Rect;
+use crate::{text, Config, Label, LabelSet, LayoutFormat, RenderContext, RenderedLabel};
+use crate::ValidateArgs;
+
+// The elements of a face label that are pictures rather than text
+const IMAGES: [&str; 3] = ["cover", "logo", "qr-code"];
+
+// The pictures that text shouldn't be set over
+const OBSTACLES: [&str; 2] = ["logo", "qr-code"];
+
+// Where each label starts in a TOML layout, so that problems with a label can point at it
+#[derive(Deserialize)]
+struct LabelSpans {
+    #[serde(default)]
+    labels: Vec<toml::Spanned<toml::Table>>,
+}
+
+// Something wrong with a layout, and the line and column it's at when that's known
+struct Problem {
+    location: Option<(usize, usize)>,
+    message: String,
+}
+
+// The line and column, both counted from 1, of a byte offset into a layout
+fn line_column(layout: &str, offset: usize) -> (usize, usize) {
+    let before = &layout[..offset.min(layout.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+// How a label is referred to in problems, by its position in the layout and its title if it has
+// one that can be read
+fn label_name(n: usize, label: &toml::Value) -> String {
+    match label.get("title").and_then(toml::Value::as_str) {
+        Some(title) => format!("label {} ('{}')", n + 1, title),
+        None => format!("label {}", n + 1),
+    }
+}
+
+// The problems with the settings in a layout that has been read. Each top level setting and each
+// label is checked on its own, so that every mistake is found rather than just the first, and
+// each can be pointed at in TOML layouts.
+fn check_settings(layout: &str, format: LayoutFormat, table: &toml::Table) -> Vec<Problem> {
+    let (setting_spans, label_spans) = match format {
+        LayoutFormat::Toml => (
+            toml::from_str::<BTreeMap<String, toml::Spanned<toml::Value>>>(layout)
+                .unwrap_or_default(),
+            toml::from_str::<LabelSpans>(layout).map_or(Vec::new(), |spans| spans.labels),
+        ),
+        _ => (BTreeMap::new(), Vec::new()),
+    };
+    let setting_location = |key: &str| {
+        setting_spans.get(key).map(|value| line_column(layout, value.span().start))
+    };
+
+    let mut problems = Vec::new();
+    for (key, value) in table.iter().filter(|(key, _)| *key != "labels") {
+        let setting = toml::Table::from_iter([
+            (key.clone(), value.clone()),
+            ("labels".to_string(), toml::Value::Array(Vec::new())),
+        ]);
+        if let Err(e) = setting.try_into::<Config>() {
+            problems.push(Problem {
+                location: setting_location(key),
+                message: format!("`{}`: {}", key, e.message()),
+            });
+        }
+    }
+
+    let labels = match table.get("labels") {
+        Some(toml::Value::Array(labels)) => labels,
+        Some(_) => {
+            problems.push(Problem {
+                location: setting_location("labels"),
+                message: "`labels` must be a list of labels, given as `[[labels]]` tables"
+                    .to_string(),
+            });
+            return problems;
+        },
+        None => {
+            problems.push(Problem {
+                location: None,
+                message: "there are no labels, add them as `[[labels]]` tables".to_string(),
+            });
+            return problems;
+        },
+    };
+    for (n, label) in labels.iter().enumerate() {
+        if let Err(e) = label.clone().try_into::<Label>() {
+            problems.push(Problem {
+                location: label_spans.get(n).map(|span| line_column(layout, span.span().start)),
+                message: format!("{}: {}", label_name(n, label), e.message()),
+            });
+        }
+    }
+    problems
+}
+
+// The problems with where the text of a drawn label ended up: lines that run off the edge of the
+// label, past where it's cut, or over the logo or QR code. Each line is named by the field that
+// it's from when the label has them.
+pub fn text_problems(label: &RenderedLabel, description: &str) -> Vec<String> {
+    let bleed = label.bleed;
+    let (right, bottom) = (label.art.width() as i32 - bleed, label.art.height() as i32 - bleed);
+    let mut fields: Vec<_> = label.elements.iter()
+        .filter(|(name, _)| !IMAGES.contains(&name.as_str()) && name.as_str() != "text-area")
+        .collect();
+    fields.sort_by_key(|(name, _)| name.as_str());
+
+    let mut problems = Vec::new();
+    for line in &label.text {
+        let bounds = text::line_bounds(line);
+        let field = fields.iter()
+            .find(|(_, rect)| rect.intersects(&bounds))
+            .map_or(String::new(), |(name, _)| format!("the {} ", name));
+
+        let off_label = bounds.x < bleed || bounds.y < bleed
+            || bounds.right() > right || bounds.bottom() > bottom;
+        if off_label {
+            problems.push(format!("{}'{}' runs off the edge of {}", field, line.text, description));
+        }
+        for name in OBSTACLES {
+            let picture = label.elements.get(name);
+            if picture.is_some_and(|rect| rect.width > 0 && rect.intersects(&bounds)) {
+                problems.push(format!("{}'{}' runs into the {} on {}",
+                    field, line.text, name, description
+                ));
+            }
+        }
+    }
+    problems
+}
+
+// The problems with a single label: a cover that can't be read, or text or images that won't
+// fit on any of the labels made for it. Text that's still too long at the smallest size is only
+// shrunk or cut so far, so it's checked for running off the label.
+pub fn label_problems(label_info: &Label, set: LabelSet, context: &RenderContext) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !label_info.cover.is_empty() {
+        if !Path::new(&label_info.cover).is_file() {
+            return vec![format!("the cover '{}' doesn't exist", label_info.cover)];
+        }
+        if let Err(e) = crate::load_cover(label_info) {
+            return vec![format!("the cover '{}' can't be read: {}", label_info.cover, e)];
+        }
+    }
+
+    for label_type in crate::label_types(label_info, set) {
+        let kind = format!("{:?}", label_type).to_lowercase();
+        let label = match crate::render_label(label_info, label_type, context) {
+            Ok(label) => label,
+            Err(e) => {
+                problems.push(format!("the {} label: {}", kind, e));
+                continue;
+            },
+        };
+
+        problems.extend(text_problems(&label, &format!("the {} label", kind)));
+
+        let (width, height) = (label.art.width() as i32, label.art.height() as i32);
+        let off_label = |bounds: &Rect| bounds.x < 0 || bounds.y < 0
+            || bounds.right() > width || bounds.bottom() > height;
+
+        // Images moved with `[layout]` can be put anywhere, including off the label
+        let mut images: Vec<_> = label.elements.iter()
+            .filter(|(name, bounds)| IMAGES.contains(&name.as_str())
+                && bounds.width > 0 && off_label(bounds))
+            .map(|(name, _)| name)
+            .collect();
+        images.sort();
+        for name in images {
+            problems.push(format!("the {} is placed off the edge of the {} label", name, kind));
+        }
+    }
+    problems
+}
+
+// Check that a layout can be made into labels, without saving any. Its settings are checked
+// first, then each label's cover is read and its text laid out to make sure it fits. Every
+// problem is listed, with where it is in the layout when that's known.
+pub fn validate(args: &ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let path = &args.layout;
+    let layout = crate::read_layout(path)?;
+    let format = LayoutFormat::from_path(path);
+
+    // A layout that can't be read at all has a single problem, which the parsers say where is
+    let table = match crate::merge_defaults(&layout, format) {
+        Ok(table) => table,
+        Err(e) => {
+            println!("{}: {}", path, e);
+            return Err(format!("{} can't be read", path).into());
+        },
+    };
+    let mut problems = check_settings(&layout, format, &table);
+
+    if problems.is_empty() {
+        let label_spans = match format {
+            LayoutFormat::Toml => toml::from_str::<LabelSpans>(&layout)?.labels,
+            _ => Vec::new(),
+        };
+        let values = match table.get("labels") {
+            Some(toml::Value::Array(labels)) => labels.clone(),
+            _ => Vec::new(),
+        };
+        let label_config: Config = table.try_into()?;
+        let theme = crate::load_theme(&args.render, &label_config)?;
+        let context = crate::render_context(&args.render, &label_config, &theme)?;
+
+        for (n, label_info) in label_config.labels.iter().enumerate() {
+            let location = label_spans.get(n).map(|span| line_column(&layout, span.span().start));
+            let name = label_name(n, &values[n]);
+            for message in label_problems(label_info, args.render.set, &context) {
+                problems.push(Problem { location, message: format!("{}: {}", name, message) });
+            }
+        }
+    }
+
+    for problem in &problems {
+        match problem.location {
+            Some((line, column)) => println!("{}:{}:{}: {}", path, line, column, problem.message),
+            None => println!("{}: {}", path, problem.message),
+        }
+    }
+    match problems.len() {
+        0 => {
+            println!("{} is valid", path);
+            Ok(())
+        },
+        1 => Err(format!("found a problem with {}", path).into()),
+        count => Err(format!("found {} problems with {}", count, path).into()),
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the files are looked at
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// How long the files have to be left alone after a change before it's acted on. Editors often
+// save in several writes, and a layout and its covers may be saved one after another
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+// A set of files and when each was last changed, to tell when any of them have been edited.
+// Files that don't exist are watched for being created.
+pub struct Watched {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl Watched {
+    pub fn new(paths: Vec<PathBuf>) -> Watched {
+        let files = paths.into_iter()
+            .map(|path| {
+                let time = modified(&path);
+                (path, time)
+            })
+            .collect();
+        Watched { files }
+    }
+
+    // Whether any of the files have changed since they were last looked at
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, time) in &mut self.files {
+            let now = modified(path);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // Wait for any of the files to change, and then for them all to stop changing
+    pub fn wait(&mut self) {
+        while !self.changed() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let mut last_change = Instant::now();
+        while last_change.elapsed() < SETTLE_TIME {
+            thread::sleep(POLL_INTERVAL);
+            if self.changed() {
+                last_change = Instant::now();
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use imageproc::image::RgbImage;
+use minifb::{Key, ScaleMode, Window, WindowOptions};
+
+use crate::watch::Watched;
+
+// How often to look for changes to the layout and covers
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Space between sheets, and around them, in sheet pixels
+const GAP: u32 = 40;
+const GAP_COLOR: u32 = 0x606060;
+
+// The tallest the window opens at, in screen pixels. It can be resized once it's open
+const WINDOW_HEIGHT: u32 = 900;
+
+// Draw every sheet side by side into a buffer of 0RGB pixels for the window
+fn compose(sheets: &[RgbImage]) -> (Vec<u32>, usize, usize) {
+    let width = sheets.iter().map(|sheet| sheet.width() + GAP).sum::<u32>() + GAP;
+    let height = sheets.iter().map(RgbImage::height).max().unwrap_or(0) + 2 * GAP;
+
+    let mut buffer = vec![GAP_COLOR; (width * height) as usize];
+    let mut left = GAP;
+    for sheet in sheets {
+        for (x, y, pixel) in sheet.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            buffer[((y + GAP) * width + left + x) as usize] =
+                (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        left += sheet.width() + GAP;
+    }
+    (buffer, width as usize, height as usize)
+}
+
+// Show sheets in a window, and draw them again whenever any of the files they're drawn from
+// change. `draw` gives the sheets and the files to watch. When drawing fails, eg. because the
+// layout is half edited, the problem is printed and the last sheets that could be drawn stay up.
+pub fn watch_preview(
+    title: &str,
+    mut draw: impl FnMut() -> Result<(Vec<RgbImage>, Vec<PathBuf>), Box<dyn Error>>
+) -> Result<(), Box<dyn Error>> {
+    let (sheets, files) = draw()?;
+    let mut watched = Watched::new(files);
+    let (mut buffer, mut width, mut height) = compose(&sheets);
+
+    let scale = (WINDOW_HEIGHT as f32 / height as f32).min(1.0);
+    let mut window = Window::new(
+        title,
+        (width as f32 * scale) as usize,
+        (height as f32 * scale) as usize,
+        WindowOptions {
+            resize: true,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..Default::default()
+        }
+    ).map_err(|e| format!("can't open a preview window: {}", e))?;
+    window.set_target_fps(30);
+
+    let mut last_poll = Instant::now();
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if last_poll.elapsed() >= POLL_INTERVAL {
+            last_poll = Instant::now();
+            if watched.changed() {
+                match draw() {
+                    Ok((sheets, files)) => {
+                        (buffer, width, height) = compose(&sheets);
+                        watched = Watched::new(files);
+                        window.set_title(title);
+                    },
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        window.set_title(&format!("{} (showing the last sheets drawn)", title));
+                    },
+                }
+            }
+        }
+        window.update_with_buffer(&buffer, width, height)?;
+    }
+    Ok(())
+}
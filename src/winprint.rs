@@ -0,0 +1,204 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::iter;
+use std::mem;
+use std::ptr;
+
+use imageproc::image::RgbImage;
+use windows_sys::Win32::Graphics::Gdi::{
+    CreateDCW, DeleteDC, GetDeviceCaps, SetStretchBltMode, StretchDIBits, BITMAPINFO,
+    BITMAPINFOHEADER, BI_RGB, DEVMODEW, DIB_RGB_COLORS, DMPAPER_USER, DM_FORMNAME, DM_IN_BUFFER,
+    DM_OUT_BUFFER, DM_PAPERLENGTH, DM_PAPERSIZE, DM_PAPERWIDTH, HALFTONE, HDC, LOGPIXELSX,
+    LOGPIXELSY, PHYSICALHEIGHT, PHYSICALOFFSETX, PHYSICALOFFSETY, PHYSICALWIDTH, SRCCOPY,
+};
+use windows_sys::Win32::Graphics::Printing::{
+    ClosePrinter, DocumentPropertiesW, GetDefaultPrinterW, OpenPrinterW, PRINTER_HANDLE,
+};
+use windows_sys::Win32::Storage::Xps::{AbortDoc, EndDoc, EndPage, StartDocW, StartPage, DOCINFOW};
+
+use crate::print::{Job, Scaling};
+
+// Text as a null terminated UTF-16 string, the way Windows takes it
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(iter::once(0)).collect()
+}
+
+fn default_printer() -> Result<Vec<u16>, Box<dyn Error>> {
+    let mut length = 0;
+    unsafe { GetDefaultPrinterW(ptr::null_mut(), &mut length) };
+    let mut name = vec![0; length as usize];
+    if length == 0 || unsafe { GetDefaultPrinterW(name.as_mut_ptr(), &mut length) } == 0 {
+        return Err("there's no default printer, give one with --printer".into());
+    }
+    Ok(name)
+}
+
+// The printer's own settings, changed to print on the paper asked for. The settings are followed
+// by data of the driver's own, so they're kept in a buffer as big as the driver says it needs
+fn settings(printer: &str, name: &[u16], job: &Job) -> Result<Vec<u64>, Box<dyn Error>> {
+    let mut handle = PRINTER_HANDLE::default();
+    if unsafe { OpenPrinterW(name.as_ptr(), &mut handle, ptr::null()) } == 0 {
+        return Err(format!("can't find a printer called '{}'", printer).into());
+    }
+
+    let size = unsafe {
+        DocumentPropertiesW(ptr::null_mut(), handle, name.as_ptr(), ptr::null_mut(), ptr::null(), 0)
+    };
+    let mut buffer = vec![0u64; (size.max(0) as usize).div_ceil(mem::size_of::<u64>())];
+    let settings = buffer.as_mut_ptr() as *mut DEVMODEW;
+    let mut read = size > 0 && unsafe {
+        DocumentPropertiesW(ptr::null_mut(), handle, name.as_ptr(), settings, ptr::null(),
+            DM_OUT_BUFFER)
+    } >= 0;
+
+    if read {
+        let settings = unsafe { &mut *settings };
+        match job.media {
+            // Paper is picked by the name of a form the printer knows, which has to fit in the
+            // settings with room for its terminator
+            Some(media) => {
+                let form = wide(media);
+                if form.len() > settings.dmFormName.len() {
+                    unsafe { ClosePrinter(handle) };
+                    return Err(format!("the paper name '{}' is too long for Windows", media)
+                        .into());
+                }
+                settings.dmFormName = [0; 32];
+                settings.dmFormName[..form.len()].copy_from_slice(&form);
+                settings.dmFields |= DM_FORMNAME;
+            },
+            // Paper sizes are given in tenths of a millimetre
+            None => {
+                let (width, height) = job.page_size;
+                let paper = unsafe { &mut settings.Anonymous1.Anonymous1 };
+                paper.dmPaperSize = DMPAPER_USER as i16;
+                paper.dmPaperWidth = (width * 10.0).round() as i16;
+                paper.dmPaperLength = (height * 10.0).round() as i16;
+                settings.dmFields |= DM_PAPERSIZE | DM_PAPERWIDTH | DM_PAPERLENGTH;
+            },
+        }
+        read = unsafe {
+            DocumentPropertiesW(ptr::null_mut(), handle, name.as_ptr(), settings, settings,
+                DM_IN_BUFFER | DM_OUT_BUFFER)
+        } >= 0;
+    }
+
+    unsafe { ClosePrinter(handle) };
+    if !read {
+        return Err(format!("can't read the settings of the printer '{}'", printer).into());
+    }
+    Ok(buffer)
+}
+
+// Draw a sheet onto a page, sized by the job's scaling and centred on the paper. GDI takes
+// bitmaps as rows of BGR pixels, each padded to a multiple of 4 bytes
+fn draw_sheet(dc: HDC, sheet: &RgbImage, job: &Job) -> Result<(), Box<dyn Error>> {
+    let caps = |index| unsafe { GetDeviceCaps(dc, index as i32) } as f32;
+    let paper = (caps(PHYSICALWIDTH), caps(PHYSICALHEIGHT));
+
+    // Device units per millimetre, which often differ across and down the page
+    let scale = (caps(LOGPIXELSX) / 25.4 / job.dpmm, caps(LOGPIXELSY) / 25.4 / job.dpmm);
+    let size = (sheet.width() as f32 * scale.0, sheet.height() as f32 * scale.1);
+    let fit = match job.scaling {
+        Scaling::None => 1.0,
+        Scaling::Fit => (paper.0 / size.0).min(paper.1 / size.1),
+        Scaling::Fill => (paper.0 / size.0).max(paper.1 / size.1),
+    };
+    let size = (size.0 * fit, size.1 * fit);
+
+    // Positions are measured from the corner of the printable area rather than of the paper
+    let x = (paper.0 - size.0) / 2.0 - caps(PHYSICALOFFSETX);
+    let y = (paper.1 - size.1) / 2.0 - caps(PHYSICALOFFSETY);
+
+    let stride = (sheet.width() as usize * 3).next_multiple_of(4);
+    let mut bits = vec![0u8; stride * sheet.height() as usize];
+    for (row, pixels) in bits.chunks_exact_mut(stride).zip(sheet.rows()) {
+        for (bgr, pixel) in row.chunks_exact_mut(3).zip(pixels) {
+            bgr.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+    }
+
+    // A negative height means the rows go from the top down, as they do in the sheet
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: sheet.width() as i32,
+            biHeight: -(sheet.height() as i32),
+            biPlanes: 1,
+            biBitCount: 24,
+            biCompression: BI_RGB,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let drawn = unsafe {
+        StartPage(dc) > 0 && {
+            SetStretchBltMode(dc, HALFTONE);
+            StretchDIBits(dc, x.round() as i32, y.round() as i32, size.0.round() as i32,
+                size.1.round() as i32, 0, 0, sheet.width() as i32, sheet.height() as i32,
+                bits.as_ptr().cast(), &info, DIB_RGB_COLORS, SRCCOPY) > 0
+        } && EndPage(dc) > 0
+    };
+    match drawn {
+        true => Ok(()),
+        false => Err("the printer wouldn't take a sheet".into()),
+    }
+}
+
+// Print each sheet as a page through the print spooler, drawing it with GDI
+pub fn print(printer: Option<&str>, job: &Job) -> Result<(), Box<dyn Error>> {
+    let name = match printer {
+        Some(printer) => wide(printer),
+        None => default_printer()?,
+    };
+    let printer = String::from_utf16_lossy(&name[..name.len() - 1]);
+    let settings = settings(&printer, &name, job)?;
+
+    let dc = unsafe {
+        CreateDCW(wide("WINSPOOL").as_ptr(), name.as_ptr(), ptr::null(), settings.as_ptr().cast())
+    };
+    if dc.is_null() {
+        return Err(format!("can't print to '{}'", printer).into());
+    }
+
+    let title = wide(job.title);
+    let document = DOCINFOW {
+        cbSize: mem::size_of::<DOCINFOW>() as i32,
+        lpszDocName: title.as_ptr(),
+        ..Default::default()
+    };
+    let printed = match unsafe { StartDocW(dc, &document) } {
+        ..=0 => Err(format!("'{}' wouldn't start printing", printer).into()),
+        _ => job.sheets.iter().try_for_each(|sheet| draw_sheet(dc, sheet, job)),
+    };
+    unsafe {
+        match printed {
+            Ok(()) => EndDoc(dc),
+            Err(_) => AbortDoc(dc),
+        };
+        DeleteDC(dc);
+    }
+    printed?;
+
+    println!("Sent to {}", printer);
+    Ok(())
+}
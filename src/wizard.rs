@@ -0,0 +1,120 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use dialoguer::{Completion, Confirm, Input};
+
+use crate::fetch::{self, FetchedLabel};
+use crate::{validate, Config, LayoutFormat, NewArgs};
+
+// Completes file paths as they're typed, as far as all of the files they could be agree
+struct PathCompletion;
+
+impl Completion for PathCompletion {
+    fn get(&self, input: &str) -> Option<String> {
+        let (dir, prefix) = match input.rfind('/') {
+            Some(slash) => input.split_at(slash + 1),
+            None => ("", input),
+        };
+        let matches: Vec<String> = fs::read_dir(if dir.is_empty() { "." } else { dir }).ok()?
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_dir() { name + "/" } else { name }
+            })
+            // Hidden files only come up when they're asked for
+            .filter(|name| name.starts_with(prefix))
+            .filter(|name| prefix.starts_with('.') || !name.starts_with('.'))
+            .collect();
+
+        let first = matches.first()?;
+        let common = matches.iter().fold(first.chars().count(), |length, name| {
+            first.chars().zip(name.chars()).take(length).take_while(|(a, b)| a == b).count()
+        });
+        let common: String = first.chars().take(common).collect();
+        (common.len() > prefix.len()).then(|| format!("{}{}", dir, common))
+    }
+}
+
+// The labels a layout has, with a new one added to the end, read the same way as the layout
+// will be once it's added so that it picks up the layout's `[defaults]`
+fn with_label(layout: &str, label: FetchedLabel) -> Result<Config, Box<dyn Error>> {
+    let layout = format!("{}\n{}", layout, fetch::label_toml(label)?);
+    crate::parse_config(&layout, LayoutFormat::Toml)
+}
+
+// Ask for the details of labels one after another, warning about anything that won't print
+// well, and add each to the end of a layout
+pub fn new_labels(args: &NewArgs) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(&args.layout);
+    if LayoutFormat::from_path(&args.layout) != LayoutFormat::Toml {
+        return Err("new can only add labels to TOML layouts".into());
+    }
+
+    loop {
+        let artist: String = Input::new().with_prompt("Artist").interact_text()?;
+        let title: String = Input::new().with_prompt("Title").interact_text()?;
+        let release_year: String = Input::new()
+            .with_prompt("Year")
+            .allow_empty(true)
+            .interact_text()?;
+        let cover: String = Input::new()
+            .with_prompt("Cover")
+            .completion_with(&PathCompletion)
+            .validate_with(|cover: &String| match Path::new(cover).is_file() {
+                true => Ok(()),
+                false => Err("there's no file there"),
+            })
+            .interact_text()?;
+        let label = || FetchedLabel {
+            title: title.clone(),
+            artist: artist.clone(),
+            release_year: Some(release_year.clone()).filter(|year| !year.is_empty()),
+            record_label: None,
+            catalog_number: None,
+            cover: Some(cover.clone()),
+            tracks: Vec::new(),
+        };
+
+        // The label is checked with the layout's own settings, like its theme
+        let layout = if path.exists() { crate::read_layout(&args.layout)? } else { String::new() };
+        let label_config = with_label(&layout, label())?;
+        let theme = crate::load_theme(&args.render, &label_config)?;
+        let context = crate::render_context(&args.render, &label_config, &theme)?;
+        let label_info = label_config.labels.last().ok_or("the label wasn't added")?;
+        let problems = validate::label_problems(label_info, args.render.set, &context);
+        for problem in &problems {
+            eprintln!("warning: {}", problem);
+        }
+
+        let question = match problems.len() {
+            0 => format!("Add it to {}?", path.display()),
+            _ => format!("Add it to {} anyway?", path.display()),
+        };
+        if Confirm::new().with_prompt(question).default(problems.is_empty()).interact()? {
+            fetch::append_label(path, label())?;
+        }
+        if !Confirm::new().with_prompt("Make another label?").default(false).interact()? {
+            return Ok(());
+        }
+    }
+}
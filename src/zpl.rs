@@ -0,0 +1,63 @@
+/*
+ * MDLabelgen: A program to generate label images for (primarily audio) MiniDiscs.
+ *
+ * Copyright (C) 2024 Matt Valentine-House
+ *
+ * SPDX-License-Identifier: GPL-2.0-only
+ *
+ * This program is free software: you can redistribute it and/or modify it under the terms of the
+ * GNU General Public License as published by the Free Software Foundation, version 2.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+ * without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+ * the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program. If
+ * not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::error::Error;
+use std::fmt::Write;
+
+use imageproc::image::RgbImage;
+
+use crate::dither::Dither;
+use crate::thermal;
+
+// Zebra desktop printers print 203 dots to the inch, on labels up to 104 mm across
+const DOTS_PER_MM: f32 = 203.0 / 25.4;
+const HEAD_DOTS: u32 = 832;
+
+// Turn a sheet into a ZPL label for a Zebra printer. The sheet is scaled to 203 dpi, turned on its
+// side if that's the only way it fits across the head, dithered to black and white, and sent as a
+// graphic field filling a label the size of the sheet. Darkness, from 0 to 30, is set for the
+// label when it's given and left as the printer has it otherwise.
+pub fn label(
+    sheet: &RgbImage,
+    dpmm: f32,
+    darkness: Option<u8>,
+    dither: Dither
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let head = format!("a Zebra's {} mm head", (HEAD_DOTS as f32 / DOTS_PER_MM).round());
+    let dots = thermal::dots(sheet, dpmm, DOTS_PER_MM, HEAD_DOTS, &head, dither)?;
+    let bytes_per_row = dots.width().div_ceil(8);
+    let total = bytes_per_row * dots.height();
+
+    let mut zpl = String::new();
+    if let Some(darkness) = darkness {
+        write!(zpl, "~SD{:02}", darkness)?;
+    }
+    writeln!(zpl, "^XA")?;
+    writeln!(zpl, "^PW{}", dots.width())?;
+    writeln!(zpl, "^LL{}", dots.height())?;
+    write!(zpl, "^FO0,0^GFA,{},{},{},", total, total, bytes_per_row)?;
+    for row in dots.rows() {
+        for byte in thermal::pack(&row.map(thermal::is_black).collect::<Vec<_>>()) {
+            write!(zpl, "{:02X}", byte)?;
+        }
+    }
+    writeln!(zpl, "^FS")?;
+    writeln!(zpl, "^XZ")?;
+    Ok(zpl.into_bytes())
+}